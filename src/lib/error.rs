@@ -0,0 +1,54 @@
+//! This module describes everything that can go wrong in this library, so callers can match on the kind of failure.
+
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use crate::model::{cache::IntegrityError, codec::Codec, import::RecordError, FormatError};
+
+/// The result of every fallible operation in this library.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Everything that can go wrong in this library.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// Reading or writing a file failed.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// The downloaded `.zip` archive could not be read.
+    #[error(transparent)]
+    Archive(#[from] zip::result::ZipError),
+    /// A streaming history file is not valid JSON, or not a list of records.
+    #[error("failed to parse `{}`", path.display())]
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    /// A single record does not match the expected schema.
+    #[error(transparent)]
+    Record(#[from] RecordError),
+    /// The header of a persistent file is invalid, or its format version is not supported.
+    #[error(transparent)]
+    Format(#[from] FormatError),
+    /// The payload of a persistent file could not be serialized.
+    #[error(transparent)]
+    Encode(#[from] rmp_serde::encode::Error),
+    /// The payload of a persistent file could not be deserialized.
+    #[error(transparent)]
+    Decode(#[from] rmp_serde::decode::Error),
+    /// Compressing or decompressing a payload failed.
+    #[error("{codec} compression failed")]
+    Compression {
+        codec: Codec,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// The content of the cache is inconsistent.
+    #[error("the cached data is inconsistent, found {} problems", .0.len())]
+    Integrity(Vec<IntegrityError>),
+    /// A search query is not a valid regular expression.
+    #[error(transparent)]
+    Regex(#[from] regex::Error),
+    /// A value given by the user could not be understood, e.g. a month or a codec.
+    #[error("{0}")]
+    InvalidInput(String),
+}
@@ -1,4 +1,7 @@
 //! This is a the functionality we use.
 
+pub mod error;
 pub mod model;
 pub mod serde;
+
+pub use error::{Error, Result};
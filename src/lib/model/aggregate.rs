@@ -0,0 +1,172 @@
+//! This module describes rankings above track level, i.e. per artist or podcast and per album or show.
+
+use std::collections::BTreeMap;
+
+use chrono::Duration;
+
+use super::{compression::CompressedEndStreamWithKindContainer, end_stream::EndStreamKind};
+
+/// The level at which streams are aggregated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// One entry per artist or podcast.
+    Artist,
+    /// One entry per album or show.
+    Album,
+}
+
+/// Represents the aggregated streaming data of an artist or podcast, or of an album or show.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Aggregate {
+    /// Whether this is a song, episode or something else.
+    pub kind: EndStreamKind,
+    /// Artist or podcast name.
+    pub artist_or_podcast: String,
+    /// Album or show name, `None` when aggregated at the artist level.
+    pub album_or_show: Option<String>,
+    /// Total duration of all tracks or episodes played.
+    pub total_ms_played: Duration,
+    /// The amount of streams.
+    pub play_count: usize,
+    /// The amount of distinct tracks or episodes that were streamed.
+    pub distinct_tracks: usize,
+}
+
+impl Aggregate {
+    /// The percentage of `total` that was spent on this entry, `0` when nothing was played at all.
+    pub fn share(&self, total: Duration) -> f64 {
+        if total.is_zero() {
+            0.0
+        } else {
+            100.0 * self.total_ms_played.num_milliseconds() as f64 / total.num_milliseconds() as f64
+        }
+    }
+}
+
+/// Represents a collection of aggregated streaming data.
+#[repr(transparent)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AggregateContainer(pub Vec<Aggregate>);
+
+impl AggregateContainer {
+    /// Aggregates the streaming data at the given level, merging every username, country and platform.
+    pub fn new(value: CompressedEndStreamWithKindContainer, level: Level) -> Self {
+        let mut acc: BTreeMap<(EndStreamKind, String, Option<String>), Aggregate> = BTreeMap::new();
+        for (kind, container) in value.by_kind() {
+            for (artist, album, _track, info) in container {
+                let album = match level {
+                    Level::Artist => None,
+                    Level::Album => Some(album),
+                };
+                let entry = acc
+                    .entry((kind.clone(), artist.clone(), album.clone()))
+                    .or_insert_with(|| Aggregate {
+                        kind: kind.clone(),
+                        artist_or_podcast: artist,
+                        album_or_show: album,
+                        total_ms_played: Duration::zero(),
+                        play_count: 0,
+                        distinct_tracks: 0,
+                    });
+                entry.total_ms_played += info.total_ms_played;
+                entry.play_count += info.play_count();
+                entry.distinct_tracks += 1;
+            }
+        }
+        Self(acc.into_values().collect())
+    }
+
+    /// Total duration of all entries.
+    pub fn total_ms_played(&self) -> Duration {
+        self.0
+            .iter()
+            .fold(Duration::zero(), |acc, x| acc + x.total_ms_played)
+    }
+
+    /// Splits the entries per `EndStreamKind`, so music and podcasts can be ranked separately.
+    pub fn by_kind(self) -> BTreeMap<EndStreamKind, AggregateContainer> {
+        let mut out: BTreeMap<EndStreamKind, AggregateContainer> = BTreeMap::new();
+        for entry in self.0 {
+            out.entry(entry.kind.clone())
+                .or_insert_with(|| AggregateContainer(Vec::new()))
+                .0
+                .push(entry);
+        }
+        out
+    }
+
+    /// Sorts the entries from most to least played, or the other way around when `reversed`.
+    pub fn sort(&mut self, reversed: bool) {
+        if reversed {
+            self.0.sort_by_key(|x| x.total_ms_played)
+        } else {
+            self.0.sort_by_key(|x| std::cmp::Reverse(x.total_ms_played))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::end_stream::{EndStream, EndStreamWithKind};
+
+    use super::*;
+
+    fn container(streams: &[(&str, &str, &str, i64)]) -> CompressedEndStreamWithKindContainer {
+        let mut out = CompressedEndStreamWithKindContainer::default();
+        for (index, (artist, album, track, ms_played)) in streams.iter().enumerate() {
+            let end_stream: EndStream = serde_json::from_value(serde_json::json!({
+                "ts": format!("2023-01-01T12:{index:02}:00Z"),
+                "username": "user",
+                "platform": "android",
+                "ms_played": ms_played,
+                "conn_country": "NL",
+                "master_metadata_track_name": track,
+                "master_metadata_album_artist_name": artist,
+                "master_metadata_album_album_name": album,
+            }))
+            .unwrap();
+            out.insert_end_stream(EndStreamWithKind::from(end_stream));
+        }
+        out
+    }
+
+    #[test]
+    fn test_aggregates_count_plays_and_distinct_tracks() {
+        let data = container(&[
+            ("Daft Punk", "Discovery", "One More Time", 30_000),
+            ("Daft Punk", "Discovery", "One More Time", 10_000),
+            ("Daft Punk", "Discovery", "Digital Love", 20_000),
+            ("Daft Punk", "Homework", "Da Funk", 15_000),
+            ("Justice", "Cross", "D.A.N.C.E.", 25_000),
+        ]);
+
+        let artists = AggregateContainer::new(data.clone(), Level::Artist);
+        let daft_punk = &artists.0[0];
+        assert_eq!(daft_punk.artist_or_podcast, "Daft Punk");
+        assert_eq!(daft_punk.album_or_show, None);
+        assert_eq!(daft_punk.total_ms_played, Duration::milliseconds(75_000));
+        assert_eq!(daft_punk.play_count, 4);
+        assert_eq!(daft_punk.distinct_tracks, 3);
+        assert_eq!(artists.total_ms_played(), Duration::milliseconds(100_000));
+        assert_eq!(daft_punk.share(artists.total_ms_played()), 75.0);
+        assert_eq!(daft_punk.share(Duration::zero()), 0.0);
+
+        let mut albums = AggregateContainer::new(data, Level::Album);
+        albums.sort(false);
+        let ranked: Vec<(&str, usize, usize)> = albums
+            .0
+            .iter()
+            .map(|x| {
+                (
+                    x.album_or_show.as_deref().unwrap_or_default(),
+                    x.play_count,
+                    x.distinct_tracks,
+                )
+            })
+            .collect();
+        assert_eq!(
+            ranked,
+            [("Discovery", 3, 2), ("Cross", 1, 1), ("Homework", 1, 1)]
+        );
+    }
+}
@@ -0,0 +1,146 @@
+//! This module describes the persistent cache, i.e. the compressed streaming data together with the exports it was built from.
+
+use std::{
+    fs::metadata,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::Result;
+
+use super::{
+    compression::CompressedEndStreamWithKindContainer, import::find_streaming_history_files,
+};
+
+/// A file of an export, as it was when the cache was built.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SourceFile {
+    /// The path relative to the export folder, or the file name of the archive.
+    pub path: PathBuf,
+    /// The size in bytes.
+    pub len: u64,
+    /// The last modification time, `None` when the platform does not support it.
+    pub modified: Option<SystemTime>,
+}
+
+impl SourceFile {
+    fn new(path: &Path, relative_to: &Path) -> Result<Self> {
+        let metadata = metadata(path)?;
+        Ok(Self {
+            path: path.strip_prefix(relative_to).unwrap_or(path).to_path_buf(),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+/// Identifies the state of an export folder or archive, so a stale cache can be detected.
+#[repr(transparent)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SourceFingerprint(pub Vec<SourceFile>);
+
+impl SourceFingerprint {
+    /// Fingerprints the streaming history files of the folder, or the archive itself.
+    pub fn new<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if path.is_file() {
+            let parent = path.parent().unwrap_or(Path::new(""));
+            return Ok(Self(vec![SourceFile::new(path, parent)?]));
+        }
+        let (found, _skipped) = find_streaming_history_files(path)?;
+        let files = found
+            .iter()
+            .map(|x| SourceFile::new(x, path))
+            .collect::<Result<_>>()?;
+        Ok(Self(files))
+    }
+}
+
+/// An inconsistency in the cached streaming data.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum IntegrityError {
+    #[error("`{track}` by `{artist}` has no streams")]
+    EmptyLog { artist: String, track: String },
+    #[error(
+        "`{track}` by `{artist}` has a total of {total} ms, but its streams add up to {sum} ms"
+    )]
+    TotalMismatch {
+        artist: String,
+        track: String,
+        total: i64,
+        sum: i64,
+    },
+}
+
+/// The content of the persistent binary file.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Cache {
+    /// The exports the data was built from, empty when unknown.
+    pub sources: Vec<SourceFingerprint>,
+    pub data: CompressedEndStreamWithKindContainer,
+}
+
+impl Cache {
+    pub fn new(source: SourceFingerprint, data: CompressedEndStreamWithKindContainer) -> Self {
+        Self {
+            sources: vec![source],
+            data,
+        }
+    }
+
+    /// Whether the data was built from this export, in its current state.
+    pub fn is_built_from(&self, source: &SourceFingerprint) -> bool {
+        self.sources.contains(source)
+    }
+
+    /// Records that an export was merged into the data.
+    pub fn add_source(&mut self, source: SourceFingerprint) {
+        if !self.is_built_from(&source) {
+            self.sources.push(source);
+        }
+    }
+
+    /// Checks that every track or episode has streams, and that its total duration matches its streams.
+    pub fn verify(&self) -> Vec<IntegrityError> {
+        let mut out = Vec::new();
+        for countries in self.data.0.values() {
+            for platforms in countries.values() {
+                for kinds in platforms.values() {
+                    for artists in kinds.values() {
+                        for (artist, albums) in artists {
+                            for tracks in albums.values() {
+                                for (track, info) in tracks {
+                                    let sum = info
+                                        .end_stream_log
+                                        .iter()
+                                        .fold(Duration::zero(), |acc, (_, x)| acc + x.ms_played);
+                                    if info.end_stream_log.is_empty() {
+                                        out.push(IntegrityError::EmptyLog {
+                                            artist: artist.clone(),
+                                            track: track.clone(),
+                                        });
+                                    } else if sum != info.total_ms_played {
+                                        out.push(IntegrityError::TotalMismatch {
+                                            artist: artist.clone(),
+                                            track: track.clone(),
+                                            total: info.total_ms_played.num_milliseconds(),
+                                            sum: sum.num_milliseconds(),
+                                        });
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        out
+    }
+}
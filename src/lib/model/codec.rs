@@ -0,0 +1,175 @@
+//! This module describes the compression codecs a persistent file can be stored with.
+
+use std::{
+    fmt::Display,
+    io::{Read, Write},
+    str::FromStr,
+};
+
+use flate2::{
+    write::{DeflateDecoder, DeflateEncoder},
+    Compression,
+};
+
+use crate::{Error, Result};
+
+use super::FormatError;
+
+/// The zstd level used when none is given, a good balance between speed and size.
+pub const DEFAULT_ZSTD_LEVEL: i32 = 3;
+
+/// The highest, i.e. slowest but smallest, zstd level.
+pub const MAX_ZSTD_LEVEL: i32 = 22;
+
+type BoxedError = Box<dyn std::error::Error + Send + Sync>;
+
+/// How the payload of a persistent file is compressed, recorded in its header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Codec {
+    /// Stored as is.
+    None,
+    /// Deflate at its best compression, the only codec before codecs were configurable.
+    Deflate,
+    /// Zstandard at the given level, from 1 (fastest) to 22 (smallest).
+    Zstd { level: i32 },
+    /// LZ4, very fast but larger.
+    Lz4,
+}
+
+impl Codec {
+    /// Every codec, with a few representative zstd levels, e.g. to compare them.
+    pub const ALL: [Codec; 6] = [
+        Codec::None,
+        Codec::Deflate,
+        Codec::Zstd { level: 1 },
+        Codec::Zstd {
+            level: DEFAULT_ZSTD_LEVEL,
+        },
+        Codec::Zstd { level: 19 },
+        Codec::Lz4,
+    ];
+
+    /// The codec and its level, as stored in the header.
+    ///
+    /// A zstd level outside of `1..=MAX_ZSTD_LEVEL` is stored as 0, `compress` refuses such a level.
+    pub fn to_header_bytes(self) -> [u8; 2] {
+        match self {
+            Codec::None => [0, 0],
+            Codec::Deflate => [1, 0],
+            Codec::Zstd { level } => [2, zstd_level(level).map_or(0, |x| x as u8)],
+            Codec::Lz4 => [3, 0],
+        }
+    }
+
+    pub fn from_header_bytes([id, level]: [u8; 2]) -> Result<Self, FormatError> {
+        match id {
+            0 => Ok(Codec::None),
+            1 => Ok(Codec::Deflate),
+            2 => Ok(Codec::Zstd {
+                level: i32::from(level),
+            }),
+            3 => Ok(Codec::Lz4),
+            x => Err(FormatError::UnknownCompression(x)),
+        }
+    }
+
+    pub fn compress(self, bytes: &[u8]) -> Result<Vec<u8>> {
+        self.try_compress(bytes)
+            .map_err(|source| Error::Compression {
+                codec: self,
+                source,
+            })
+    }
+
+    pub fn decompress(self, compressed_bytes: &[u8]) -> Result<Vec<u8>> {
+        self.try_decompress(compressed_bytes)
+            .map_err(|source| Error::Compression {
+                codec: self,
+                source,
+            })
+    }
+
+    fn try_compress(self, bytes: &[u8]) -> Result<Vec<u8>, BoxedError> {
+        match self {
+            Codec::None => Ok(bytes.to_vec()),
+            Codec::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::with_capacity(128), Compression::best());
+                encoder.write_all(bytes)?;
+                Ok(encoder.finish()?)
+            }
+            Codec::Zstd { level } => Ok(zstd::encode_all(bytes, zstd_level(level)?)?),
+            Codec::Lz4 => Ok(lz4_flex::compress_prepend_size(bytes)),
+        }
+    }
+
+    fn try_decompress(self, compressed_bytes: &[u8]) -> Result<Vec<u8>, BoxedError> {
+        match self {
+            Codec::None => Ok(compressed_bytes.to_vec()),
+            Codec::Deflate => {
+                let mut decoder = DeflateDecoder::new(Vec::with_capacity(128));
+                decoder.write_all(compressed_bytes)?;
+                Ok(decoder.finish()?)
+            }
+            Codec::Zstd { .. } => {
+                let mut out = Vec::with_capacity(128);
+                zstd::Decoder::new(compressed_bytes)?.read_to_end(&mut out)?;
+                Ok(out)
+            }
+            Codec::Lz4 => Ok(lz4_flex::decompress_size_prepended(compressed_bytes)?),
+        }
+    }
+}
+
+impl Default for Codec {
+    fn default() -> Self {
+        Codec::Zstd {
+            level: DEFAULT_ZSTD_LEVEL,
+        }
+    }
+}
+
+impl Display for Codec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Codec::None => write!(f, "none"),
+            Codec::Deflate => write!(f, "deflate"),
+            Codec::Zstd { level } => write!(f, "zstd:{level}"),
+            Codec::Lz4 => write!(f, "lz4"),
+        }
+    }
+}
+
+/// Parses `none`, `deflate`, `lz4`, `zstd` or `zstd:<LEVEL>`.
+impl FromStr for Codec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            None if s == "none" => Ok(Codec::None),
+            None if s == "deflate" => Ok(Codec::Deflate),
+            None if s == "lz4" => Ok(Codec::Lz4),
+            None if s == "zstd" => Ok(Codec::Zstd {
+                level: DEFAULT_ZSTD_LEVEL,
+            }),
+            Some(("zstd", level)) => level
+                .parse()
+                .map_err(|_| Error::InvalidInput(format!("Invalid zstd level `{level}`")))
+                .and_then(zstd_level)
+                .map(|level| Codec::Zstd { level }),
+            _ => Err(Error::InvalidInput(format!(
+                "Unknown codec `{s}`, expected `none`, `deflate`, `lz4`, `zstd` or `zstd:<LEVEL>`"
+            ))),
+        }
+    }
+}
+
+/// Checks that the zstd level is within `1..=MAX_ZSTD_LEVEL`.
+fn zstd_level(level: i32) -> Result<i32> {
+    if (1..=MAX_ZSTD_LEVEL).contains(&level) {
+        Ok(level)
+    } else {
+        Err(Error::InvalidInput(format!(
+            "The zstd level should be from 1 to {MAX_ZSTD_LEVEL}, not {level}"
+        )))
+    }
+}
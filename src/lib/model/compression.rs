@@ -2,20 +2,25 @@
 //!
 //! This is so we can interact with the Spotify streaming data.
 
-use std::{collections::BTreeMap, net::IpAddr, ops::AddAssign};
+use std::{collections::BTreeMap, net::IpAddr, ops::AddAssign, path::Path};
 
 use chrono::{Duration, NaiveDateTime};
 
-use eyre::Result;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    model::end_stream::EndStreamKind,
+    model::{end_stream::EndStreamKind, Persist},
     serde::{deserialization::duration_deserialization, serialization::duration_serialization},
+    Result,
 };
 
-use super::end_stream::{
-    EndStreamWithKind, EndStreamWithKindContainer, FromFolderJson, INITIAL_VEC_CAP,
+use super::{
+    end_stream::{
+        open_zip, stream_file, stream_folder, stream_zip, stream_zip_member, EndStreamWithKind,
+        EndStreamWithKindContainer, FromFolderJson, FromZipJson, INITIAL_VEC_CAP,
+    },
+    import::{find_streaming_history_files, ImportOptions, ImportReport},
 };
 
 /// Represents a log entry for a streaming event, including play duration and reasons.
@@ -40,31 +45,44 @@ pub struct EndStreamLogEntry {
     pub incognito_mode: Option<bool>,
 }
 
+impl EndStreamLogEntry {
+    /// Whether this was played long enough to count as a stream, e.g. Spotify uses 30 seconds.
+    pub fn counts_as_stream(&self, threshold: Duration) -> bool {
+        self.ms_played >= threshold
+    }
+}
+
 /// Represents a log of streaming events indexed by timestamp.
 ///
+/// Several streams can end in the same second, e.g. when skipping through a playlist, so every timestamp holds a list of entries.
+///
 /// # Examples
 ///
 /// ```rust
 /// use spotify_stats::model::compression::{EndStreamLog, EndStreamLogEntry};
 /// use chrono::{NaiveDateTime, Duration};
-/// use std::collections::BTreeMap;
 ///
-/// let mut log_map = BTreeMap::new();
-/// log_map.insert(
-///     NaiveDateTime::parse_from_str("2013-05-03T16:35:29Z", "%Y-%m-%dT%H:%M:%SZ").unwrap(),
-///     EndStreamLogEntry{
-///         total_ms_played: Duration::seconds(180),
-///         reason_start: Some("trackstart".to_string()),
-///         reason_end: Some("trackdone".to_string()),
-///     },
-/// );
-/// let log = EndStreamLog(log_map);
+/// let entry = EndStreamLogEntry{
+///     ms_played: Duration::seconds(180),
+///     reason_start: Some("trackstart".to_string()),
+///     reason_end: Some("trackdone".to_string()),
+///     shuffle: None,
+///     skipped: None,
+///     offline: None,
+///     ip_addr_decrypted: None,
+///     user_agent_decrypted: None,
+///     offline_timestamp: None,
+///     incognito_mode: None,
+/// };
+/// let ts = NaiveDateTime::parse_from_str("2013-05-03T16:35:29Z", "%Y-%m-%dT%H:%M:%SZ").unwrap();
+/// let log = EndStreamLog::from_iter([(ts, entry.clone()), (ts, entry)]);
+/// assert_eq!(log.len(), 2);
 /// ```
 #[repr(transparent)]
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Default)]
 pub struct EndStreamLog(
-    /// A BTreeMap where the key is the timestamp and the value is a `LogEntry`.
-    pub BTreeMap<NaiveDateTime, EndStreamLogEntry>,
+    /// A BTreeMap where the key is the timestamp and the value are the `LogEntry`s ending at that timestamp.
+    pub BTreeMap<NaiveDateTime, Vec<EndStreamLogEntry>>,
 );
 
 impl EndStreamLog {
@@ -79,23 +97,61 @@ impl EndStreamLog {
     }
 
     fn insert(&mut self, key: NaiveDateTime, value: EndStreamLogEntry) {
-        assert!(self.0.insert(key, value).is_none())
+        self.0.entry(key).or_default().push(value)
+    }
+
+    /// Moves all entries of `other` into `self`, keeping entries that share a timestamp.
+    pub fn append(&mut self, other: Self) {
+        for (key, mut values) in other.0 {
+            self.0.entry(key).or_default().append(&mut values);
+        }
+    }
+
+    /// The amount of entries, i.e. streams.
+    pub fn len(&self) -> usize {
+        self.0.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Iterates over all entries in chronological order.
+    pub fn iter(&self) -> impl Iterator<Item = (&NaiveDateTime, &EndStreamLogEntry)> {
+        self.0
+            .iter()
+            .flat_map(|(key, values)| values.iter().map(move |value| (key, value)))
+    }
+
+    /// The amount of streams that were played for at least `threshold`.
+    pub fn stream_count(&self, threshold: Duration) -> usize {
+        self.iter()
+            .filter(|(_, x)| x.counts_as_stream(threshold))
+            .count()
     }
 }
 
 impl IntoIterator for EndStreamLog {
     type Item = (NaiveDateTime, EndStreamLogEntry);
 
-    type IntoIter = std::collections::btree_map::IntoIter<NaiveDateTime, EndStreamLogEntry>;
+    type IntoIter = <Vec<(NaiveDateTime, EndStreamLogEntry)> as IntoIterator>::IntoIter;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.0.into_iter()
+        let mut acc = Vec::with_capacity(INITIAL_VEC_CAP);
+        for (key, values) in self.0 {
+            acc.extend(values.into_iter().map(|value| (key, value)));
+        }
+        acc.into_iter()
     }
 }
 
 impl FromIterator<(NaiveDateTime, EndStreamLogEntry)> for EndStreamLog {
     fn from_iter<T: IntoIterator<Item = (NaiveDateTime, EndStreamLogEntry)>>(iter: T) -> Self {
-        EndStreamLog(BTreeMap::from_iter(iter))
+        let mut out = Self::new();
+        for (key, value) in iter {
+            out.insert(key, value);
+        }
+        out
     }
 }
 
@@ -112,6 +168,7 @@ impl FromIterator<(NaiveDateTime, EndStreamLogEntry)> for EndStreamLog {
 ///     end_stream_log: EndStreamLog(BTreeMap::new()), // Empty log for illustration purposes
 ///     total_ms_played: Duration::milliseconds(180),
 ///     spotify_track_uri: Some("spotify:track:example_uri".to_string()),
+///     spotify_episode_uri: None,
 /// };
 /// ```
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
@@ -130,33 +187,53 @@ pub struct AssocInfo {
     pub end_stream_log: EndStreamLog, // log
 }
 
+impl AssocInfo {
+    /// The amount of times this track or episode has been streamed.
+    pub fn play_count(&self) -> usize {
+        self.end_stream_log.len()
+    }
+
+    /// The timestamp of the first stream, if any.
+    pub fn first_play(&self) -> Option<NaiveDateTime> {
+        self.end_stream_log.0.keys().next().copied()
+    }
+
+    /// The timestamp of the last stream, if any.
+    pub fn last_play(&self) -> Option<NaiveDateTime> {
+        self.end_stream_log.0.keys().next_back().copied()
+    }
+}
+
+/// The tracks or episodes of a single kind, grouped by artist and album.
+pub type ArtistMap = BTreeMap<
+    String, // artist
+    BTreeMap<
+        String, // album
+        BTreeMap<
+            String, // track
+            AssocInfo,
+        >,
+    >,
+>;
+
+/// The streams of a single username and country, grouped by platform and kind.
+pub type PlatformMap = BTreeMap<
+    String,
+    // platform (maybe remove, because has structure that is easily compressable, for example:
+    // `Android OS 4.1.2 API 16 (samsung, GT-I8260)`
+    // `Android OS 9 API 28 (samsung, SM-G950F)`
+    BTreeMap<EndStreamKind, ArtistMap>,
+>;
+
 /// Represents streaming data in a nested structure, grouped by artist, album, and track.
 #[repr(transparent)]
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Default)]
 pub struct CompressedEndStreamWithKindContainer(
     pub  BTreeMap<
         String, // username
         BTreeMap<
             String, // conn_country
-            BTreeMap<
-                String,
-                // platform (maybe remove, because has structure that is easily compressable, for example:
-                // `Android OS 4.1.2 API 16 (samsung, GT-I8260)`
-                // `Android OS 9 API 28 (samsung, SM-G950F)`
-                BTreeMap<
-                    EndStreamKind,
-                    BTreeMap<
-                        String, // artist
-                        BTreeMap<
-                            String, // album
-                            BTreeMap<
-                                String, // track
-                                AssocInfo,
-                            >,
-                        >,
-                    >,
-                >,
-            >,
+            PlatformMap,
         >,
     >,
 );
@@ -166,6 +243,27 @@ impl CompressedEndStreamWithKindContainer {
         Self(BTreeMap::new())
     }
 
+    /// Inserts a single stream, returns `false` when it is missing its artist, album or track name.
+    pub fn insert_end_stream(&mut self, x: EndStreamWithKind) -> bool {
+        let Some((key1, key2, key3)) = x.names() else {
+            return false;
+        };
+        let (key1, key2, key3) = (key1.clone(), key2.clone(), key3.clone());
+        let info = AssocInfo::from(&x);
+        self.insert(
+            x.end_stream.username,
+            x.end_stream.conn_country,
+            x.end_stream.platform,
+            x.kind,
+            key1,
+            key2,
+            key3,
+            info,
+        );
+        true
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn insert(
         &mut self,
         username: String,
@@ -194,16 +292,130 @@ impl CompressedEndStreamWithKindContainer {
             .and_modify(|x| *x += info.clone())
             .or_insert(info);
     }
+
+    /// Groups the streaming data by `EndStreamKind`, merging the entries of every username, country and platform.
+    pub fn by_kind(self) -> BTreeMap<EndStreamKind, CompressedEndStreamContainer> {
+        let mut out: BTreeMap<EndStreamKind, CompressedEndStreamContainer> = BTreeMap::new();
+        for (_, _, _, kind, artist, album, track, info) in self {
+            out.entry(kind)
+                .or_insert_with(CompressedEndStreamContainer::new)
+                .insert(artist, album, track, info);
+        }
+        out
+    }
+}
+
+impl CompressedEndStreamWithKindContainer {
+    /// Recursively reads all streaming history files in the folder, inserting every record as soon as it is read.
+    pub fn read_folder<P>(folder: P, options: &ImportOptions) -> Result<(Self, ImportReport)>
+    where
+        P: AsRef<Path>,
+    {
+        if !options.parallel {
+            let mut out = Self::new();
+            let report = stream_folder(folder, options, |x| {
+                out.insert_end_stream(x);
+            })?;
+            return Ok((out, report));
+        }
+        let (files, skipped) = find_streaming_history_files(folder)?;
+        let partials = files
+            .into_par_iter()
+            .map(|path| {
+                let mut out = Self::new();
+                let mut report = ImportReport::default();
+                stream_file(path, options, &mut report, |x| {
+                    out.insert_end_stream(x);
+                })?;
+                Ok((out, report))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let report = ImportReport {
+            skipped,
+            ..Default::default()
+        };
+        Ok(Self::append_all((Self::new(), report), partials))
+    }
+
+    /// Reads all streaming history members of the archive, inserting every record as soon as it is read.
+    pub fn read_zip<P>(archive: P, options: &ImportOptions) -> Result<(Self, ImportReport)>
+    where
+        P: AsRef<Path>,
+    {
+        if !options.parallel {
+            let mut out = Self::new();
+            let report = stream_zip(archive, options, |x| {
+                out.insert_end_stream(x);
+            })?;
+            return Ok((out, report));
+        }
+        let archive = archive.as_ref();
+        let len = open_zip(archive)?.len();
+        let partials = (0..len)
+            .into_par_iter()
+            .map(|index| {
+                // Every thread needs its own handle to the archive.
+                let mut zip = open_zip(archive)?;
+                let mut out = Self::new();
+                let mut report = ImportReport::default();
+                stream_zip_member(&mut zip, index, options, &mut report, |x| {
+                    out.insert_end_stream(x);
+                })?;
+                Ok((out, report))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self::append_all(
+            (Self::new(), ImportReport::default()),
+            partials,
+        ))
+    }
+
+    /// Merges the partial results in order, so the outcome is identical to reading everything one after another.
+    fn append_all(
+        (mut out, mut report): (Self, ImportReport),
+        partials: Vec<(Self, ImportReport)>,
+    ) -> (Self, ImportReport) {
+        for (partial, partial_report) in partials {
+            out.append(partial);
+            report.append(partial_report);
+        }
+        (out, report)
+    }
+
+    /// Moves all entries of `other` into `self`, the streams of `other` come after those of `self`.
+    pub fn append(&mut self, other: Self) {
+        for (username, conn_country, platform, kind, artist, album, track, info) in other {
+            self.insert(
+                username,
+                conn_country,
+                platform,
+                kind,
+                artist,
+                album,
+                track,
+                info,
+            );
+        }
+    }
 }
 
 impl FromFolderJson for CompressedEndStreamWithKindContainer {
     fn from_folder_of_json<P>(folder: P) -> Result<Self>
     where
         Self: Sized,
-        P: AsRef<std::path::Path>,
+        P: AsRef<Path>,
+    {
+        Ok(Self::read_folder(folder, &ImportOptions::default())?.0)
+    }
+}
+
+impl FromZipJson for CompressedEndStreamWithKindContainer {
+    fn from_zip_of_json<P>(archive: P) -> Result<Self>
+    where
+        Self: Sized,
+        P: AsRef<Path>,
     {
-        let x = EndStreamWithKindContainer::from_folder_of_json(folder)?;
-        Ok(Self::from(x))
+        Ok(Self::read_zip(archive, &ImportOptions::default())?.0)
     }
 }
 
@@ -212,58 +424,30 @@ impl From<EndStreamWithKindContainer> for CompressedEndStreamWithKindContainer {
     fn from(value: EndStreamWithKindContainer) -> Self {
         let mut out = Self::new();
         for x in value {
-            let (key1, key2, key3) = match x.kind {
-                EndStreamKind::EndSong | EndStreamKind::EndVideoOrElse => (
-                    &x.end_stream.master_metadata_album_artist_name,
-                    &x.end_stream.master_metadata_album_album_name,
-                    &x.end_stream.master_metadata_track_name,
-                ),
-                EndStreamKind::EndEpisode => (
-                    &x.end_stream.master_metadata_album_artist_name,
-                    &x.end_stream.episode_show_name,
-                    &x.end_stream.episode_name,
-                ),
-            };
-            let info = AssocInfo::from(&x);
-            if let (Some(key1), Some(key2), Some(key3)) = (key1, key2, key3) {
-                out.insert(
-                    x.end_stream.username,
-                    x.end_stream.conn_country,
-                    x.end_stream.platform,
-                    x.kind,
-                    key1.clone(),
-                    key2.clone(),
-                    key3.clone(),
-                    info,
-                )
-            }
+            out.insert_end_stream(x);
         }
         out
     }
 }
 
+/// A single track or episode of `CompressedEndStreamWithKindContainer`, together with every key leading up to it.
+///
+/// The keys are the username, conn_country, platform, kind, artist, album and track.
+pub type ContainerEntry = (
+    String,
+    String,
+    String,
+    EndStreamKind,
+    String,
+    String,
+    String,
+    AssocInfo,
+);
+
 impl IntoIterator for CompressedEndStreamWithKindContainer {
-    type Item = (
-        String,
-        String,
-        String,
-        EndStreamKind,
-        String,
-        String,
-        String,
-        AssocInfo,
-    );
-
-    type IntoIter = <Vec<(
-        String,
-        String,
-        String,
-        EndStreamKind,
-        String,
-        String,
-        String,
-        AssocInfo,
-    )> as IntoIterator>::IntoIter;
+    type Item = ContainerEntry;
+
+    type IntoIter = <Vec<ContainerEntry> as IntoIterator>::IntoIter;
 
     fn into_iter(self) -> Self::IntoIter {
         let mut acc = Vec::with_capacity(INITIAL_VEC_CAP);
@@ -295,34 +479,8 @@ impl IntoIterator for CompressedEndStreamWithKindContainer {
     }
 }
 
-impl
-    FromIterator<(
-        String,
-        String,
-        String,
-        EndStreamKind,
-        String,
-        String,
-        String,
-        AssocInfo,
-    )> for CompressedEndStreamWithKindContainer
-{
-    fn from_iter<
-        T: IntoIterator<
-            Item = (
-                String,
-                String,
-                String,
-                EndStreamKind,
-                String,
-                String,
-                String,
-                AssocInfo,
-            ),
-        >,
-    >(
-        iter: T,
-    ) -> Self {
+impl FromIterator<ContainerEntry> for CompressedEndStreamWithKindContainer {
+    fn from_iter<T: IntoIterator<Item = ContainerEntry>>(iter: T) -> Self {
         let mut out = CompressedEndStreamWithKindContainer::new();
         for (username, conn_country, platform, kind, artist, album, track, info) in iter {
             out.insert(
@@ -433,13 +591,24 @@ impl From<&EndStreamWithKind> for AssocInfo {
 impl AddAssign for AssocInfo {
     fn add_assign(&mut self, rhs: Self) {
         self.total_ms_played = self.total_ms_played + rhs.total_ms_played;
-        self.end_stream_log.0.extend(rhs.end_stream_log.0);
+        self.end_stream_log.append(rhs.end_stream_log);
     }
 }
 
 /// Represents cleaned Spotify entry data, including artist, album, track, playtime, and log.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+///
+/// The `username`, `conn_country` and `platform` are `None` when the entry has been merged over that dimension,
+/// see [`EndStreamKindCompressedLogContainer::merge`].
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct EndStreamKindCompressedLog {
+    /// Spotify username that streamed the track.
+    pub username: Option<String>,
+    /// Country code of the country where the track was streamed.
+    pub conn_country: Option<String>,
+    /// Platform used when streaming the track.
+    pub platform: Option<String>,
+    /// Whether this is a song, episode or something else.
+    pub kind: EndStreamKind,
     /// Artist name associated with the played track.
     pub artist_or_podcast: String,
     /// Album name associated with the played track.
@@ -456,6 +625,17 @@ pub struct EndStreamKindCompressedLog {
     pub log: EndStreamLog,
 }
 
+/// The default minimal play time for a stream to count, the same as Spotify uses for royalties.
+pub const STREAM_THRESHOLD_MS: i64 = 30_000;
+
+/// The dimensions of a `EndStreamKindCompressedLog` that can be kept apart, or merged together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Dimension {
+    Username,
+    Country,
+    Platform,
+}
+
 /// Represents a collection of cleaned Spotify entries.
 #[repr(transparent)]
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
@@ -464,13 +644,67 @@ pub struct EndStreamKindCompressedLogContainer(
     pub Vec<EndStreamKindCompressedLog>,
 );
 
-/// Convert `FoldedStreamingData` into `CleanedStreamingData`.
+impl Persist for EndStreamKindCompressedLogContainer {}
+
+impl EndStreamKindCompressedLogContainer {
+    /// Merges all entries that describe the same track, only keeping the given dimensions apart.
+    ///
+    /// Passing no dimensions results in one entry per track, i.e. a ranking across all dimensions.
+    pub fn merge(self, keep: &[Dimension]) -> Self {
+        let mut acc: BTreeMap<_, EndStreamKindCompressedLog> = BTreeMap::new();
+        for mut entry in self.0 {
+            if !keep.contains(&Dimension::Username) {
+                entry.username = None;
+            }
+            if !keep.contains(&Dimension::Country) {
+                entry.conn_country = None;
+            }
+            if !keep.contains(&Dimension::Platform) {
+                entry.platform = None;
+            }
+            let key = (
+                entry.username.clone(),
+                entry.conn_country.clone(),
+                entry.platform.clone(),
+                entry.kind.clone(),
+                entry.artist_or_podcast.clone(),
+                entry.album_or_show.clone(),
+                entry.track_or_episode.clone(),
+            );
+            if let Some(existing) = acc.get_mut(&key) {
+                existing.total_ms_played += entry.total_ms_played;
+                existing.log.append(entry.log);
+            } else {
+                acc.insert(key, entry);
+            }
+        }
+        Self(acc.into_values().collect())
+    }
+}
+
+/// Convert `CompressedEndStreamWithKindContainer` into `EndStreamKindCompressedLogContainer`, one entry per leaf.
 impl From<CompressedEndStreamWithKindContainer> for EndStreamKindCompressedLogContainer {
     fn from(value: CompressedEndStreamWithKindContainer) -> Self {
-        for _x in value {
-            todo!()
-        }
-        todo!()
+        Self(
+            value
+                .into_iter()
+                .map(
+                    |(username, conn_country, platform, kind, artist, album, track, info)| {
+                        EndStreamKindCompressedLog {
+                            username: Some(username),
+                            conn_country: Some(conn_country),
+                            platform: Some(platform),
+                            kind,
+                            artist_or_podcast: artist,
+                            album_or_show: album,
+                            track_or_episode: track,
+                            total_ms_played: info.total_ms_played,
+                            log: info.end_stream_log,
+                        }
+                    },
+                )
+                .collect(),
+        )
     }
 }
 
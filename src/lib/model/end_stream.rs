@@ -1,20 +1,39 @@
 use std::{
-    fs::{read_dir, read_to_string},
+    fmt::Display,
+    fs::File,
+    io::{BufReader, Read, Seek},
     net::IpAddr,
-    path::Path,
+    path::{Path, PathBuf},
 };
 
 use chrono::{Duration, NaiveDateTime};
 
-use crate::serde::{
-    deserialization::{duration_deserialization, naive_date_time_deserialization},
-    serialization::{duration_serialization, naive_date_time_serialization},
+use crate::{
+    model::{
+        import::{
+            find_streaming_history_files, ImportOptions, ImportReport, ImportedFile, RecordError,
+        },
+        legacy::{LegacyMusic, LegacyPodcast, LegacyStream, LegacyStreamContainer},
+        Persist,
+    },
+    serde::{
+        deserialization::{duration_deserialization, naive_date_time_deserialization},
+        serialization::{duration_serialization, naive_date_time_serialization},
+    },
+    Error, Result,
 };
-use eyre::Result;
-use serde::{Deserialize, Serialize};
+use serde::{
+    de::{DeserializeOwned, Error as _, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use zip::ZipArchive;
 
 pub const INITIAL_VEC_CAP: usize = 128;
 
+/// The file name prefixes of the Extended Streaming History, e.g. `Streaming_History_Audio_2023_1.json`.
+pub const EXTENDED_FILE_PREFIXES: [&str; 2] =
+    ["Streaming_History_Audio_", "Streaming_History_Video_"];
+
 pub trait FromFolderJson {
     fn from_folder_of_json<P>(folder: P) -> Result<Self>
     where
@@ -22,6 +41,22 @@ pub trait FromFolderJson {
         P: AsRef<Path>;
 }
 
+pub trait FromZipJson {
+    fn from_zip_of_json<P>(archive: P) -> Result<Self>
+    where
+        Self: Sized,
+        P: AsRef<Path>;
+}
+
+/// Whether the file name belongs to the Extended Streaming History, or the account data streaming history.
+pub fn is_streaming_history_file_name(file_name: &str) -> bool {
+    let is_extended = EXTENDED_FILE_PREFIXES
+        .iter()
+        .any(|prefix| file_name.starts_with(prefix))
+        && file_name.ends_with(".json");
+    is_extended || LegacyStreamContainer::is_legacy_file_name(file_name)
+}
+
 /// Represents a singular EndStream: [EndTrack: [EndSong || EndEpisode] || EndVideo].
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 pub struct EndStream {
@@ -123,28 +158,19 @@ pub struct EndStream {
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 pub struct EndStreamContainer(pub Vec<EndStream>);
 
-impl EndStreamContainer {
-    fn new() -> Self {
-        Self(Vec::with_capacity(INITIAL_VEC_CAP))
-    }
-}
-
 impl FromFolderJson for EndStreamContainer {
     fn from_folder_of_json<P>(folder: P) -> Result<Self>
     where
         P: AsRef<Path>,
     {
-        let mut out = Self::new();
-        for maybe_file in read_dir(&folder)? {
-            let file = maybe_file?;
-            let content = read_to_string(file.path())?;
-            let raw = serde_json::from_str::<Self>(&content)?;
-            out.0.extend(raw.into_iter())
-        }
-        Ok(out)
+        let (with_kind, _) =
+            EndStreamWithKindContainer::read_folder(folder, &ImportOptions::default())?;
+        Ok(with_kind.into_iter().map(|x| x.end_stream).collect())
     }
 }
 
+impl Persist for EndStreamContainer {}
+
 impl IntoIterator for EndStreamContainer {
     type Item = EndStream;
 
@@ -168,12 +194,43 @@ pub enum EndStreamKind {
     EndVideoOrElse,
 }
 
+impl Display for EndStreamKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EndStreamKind::EndSong => write!(f, "Song"),
+            EndStreamKind::EndEpisode => write!(f, "Episode"),
+            EndStreamKind::EndVideoOrElse => write!(f, "Video/Other"),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 pub struct EndStreamWithKind {
     pub kind: EndStreamKind,
     pub end_stream: EndStream,
 }
 
+impl EndStreamWithKind {
+    /// The artist, album and track names, or the podcast, show and episode names.
+    ///
+    /// Returns `None` when any of these is missing.
+    pub fn names(&self) -> Option<(&String, &String, &String)> {
+        let (key1, key2, key3) = match self.kind {
+            EndStreamKind::EndSong | EndStreamKind::EndVideoOrElse => (
+                &self.end_stream.master_metadata_album_artist_name,
+                &self.end_stream.master_metadata_album_album_name,
+                &self.end_stream.master_metadata_track_name,
+            ),
+            EndStreamKind::EndEpisode => (
+                &self.end_stream.master_metadata_album_artist_name,
+                &self.end_stream.episode_show_name,
+                &self.end_stream.episode_name,
+            ),
+        };
+        Some((key1.as_ref()?, key2.as_ref()?, key3.as_ref()?))
+    }
+}
+
 impl From<EndStream> for EndStreamWithKind {
     fn from(value: EndStream) -> Self {
         let x = if value.master_metadata_album_artist_name.is_some()
@@ -196,22 +253,257 @@ impl From<EndStream> for EndStreamWithKind {
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 pub struct EndStreamWithKindContainer(pub Vec<EndStreamWithKind>);
 
+impl FromFolderJson for EndStreamWithKindContainer {
+    fn from_folder_of_json<P>(folder: P) -> Result<Self>
+    where
+        Self: Sized,
+        P: AsRef<Path>,
+    {
+        Ok(Self::read_folder(folder, &ImportOptions::default())?.0)
+    }
+}
+
+impl FromZipJson for EndStreamWithKindContainer {
+    fn from_zip_of_json<P>(archive: P) -> Result<Self>
+    where
+        Self: Sized,
+        P: AsRef<Path>,
+    {
+        Ok(Self::read_zip(archive, &ImportOptions::default())?.0)
+    }
+}
+
 impl EndStreamWithKindContainer {
     fn new() -> Self {
         Self(Vec::with_capacity(INITIAL_VEC_CAP))
     }
-}
 
-impl FromFolderJson for EndStreamWithKindContainer {
-    fn from_folder_of_json<P>(folder: P) -> Result<Self>
+    /// Recursively reads all streaming history files in the folder, skipping unknown files.
+    pub fn read_folder<P>(folder: P, options: &ImportOptions) -> Result<(Self, ImportReport)>
     where
-        Self: Sized,
         P: AsRef<Path>,
     {
-        Ok(Self::from(EndStreamContainer::from_folder_of_json(folder)?))
+        let mut out = Self::new();
+        let report = stream_folder(folder, options, |x| out.0.push(x))?;
+        Ok((out, report))
+    }
+
+    /// Reads all streaming history members of the archive, skipping the PDF and other files.
+    pub fn read_zip<P>(archive: P, options: &ImportOptions) -> Result<(Self, ImportReport)>
+    where
+        P: AsRef<Path>,
+    {
+        let mut out = Self::new();
+        let report = stream_zip(archive, options, |x| out.0.push(x))?;
+        Ok((out, report))
+    }
+}
+
+/// Recursively reads all streaming history files in the folder, skipping unknown files, and passes every record to `sink` as soon as it is read.
+pub fn stream_folder<P, F>(folder: P, options: &ImportOptions, mut sink: F) -> Result<ImportReport>
+where
+    P: AsRef<Path>,
+    F: FnMut(EndStreamWithKind),
+{
+    let (files, skipped) = find_streaming_history_files(folder)?;
+    let mut report = ImportReport {
+        skipped,
+        ..Default::default()
+    };
+    for path in files {
+        stream_file(path, options, &mut report, &mut sink)?;
+    }
+    Ok(report)
+}
+
+/// Reads a single streaming history file, and passes every record to `sink` as soon as it is read.
+pub fn stream_file<F>(
+    path: PathBuf,
+    options: &ImportOptions,
+    report: &mut ImportReport,
+    mut sink: F,
+) -> Result<()>
+where
+    F: FnMut(EndStreamWithKind),
+{
+    let reader = BufReader::new(File::open(&path)?);
+    let records = stream_json(&path, reader, options, report, &mut sink)?;
+    report.files.push(ImportedFile { path, records });
+    Ok(())
+}
+
+/// Reads all streaming history members of the archive, skipping the PDF and other files, and passes every record to `sink` as soon as it is read.
+pub fn stream_zip<P, F>(archive: P, options: &ImportOptions, mut sink: F) -> Result<ImportReport>
+where
+    P: AsRef<Path>,
+    F: FnMut(EndStreamWithKind),
+{
+    let mut report = ImportReport::default();
+    let mut archive = open_zip(archive)?;
+    for index in 0..archive.len() {
+        stream_zip_member(&mut archive, index, options, &mut report, &mut sink)?;
+    }
+    Ok(report)
+}
+
+pub fn open_zip<P>(archive: P) -> Result<ZipArchive<BufReader<File>>>
+where
+    P: AsRef<Path>,
+{
+    Ok(ZipArchive::new(BufReader::new(File::open(archive)?))?)
+}
+
+/// Reads a single member of the archive, when it is a streaming history file, and passes every record to `sink` as soon as it is read.
+pub fn stream_zip_member<R, F>(
+    archive: &mut ZipArchive<R>,
+    index: usize,
+    options: &ImportOptions,
+    report: &mut ImportReport,
+    mut sink: F,
+) -> Result<()>
+where
+    R: Read + Seek,
+    F: FnMut(EndStreamWithKind),
+{
+    let file = archive.by_index(index)?;
+    if !file.is_file() {
+        return Ok(());
+    }
+    // Members are stored with their full path inside the archive, e.g. `MyData/Streaming_History_Audio_2023.json`.
+    let path = PathBuf::from(file.name());
+    if path
+        .file_name()
+        .and_then(|x| x.to_str())
+        .is_some_and(is_streaming_history_file_name)
+    {
+        let records = stream_json(&path, BufReader::new(file), options, report, &mut sink)?;
+        report.files.push(ImportedFile { path, records });
+    } else {
+        report.skipped.push(path);
+    }
+    Ok(())
+}
+
+/// Parses the JSON content of a streaming history file one record at a time, the format is determined by the file name.
+///
+/// Only a single record is held in memory at once. Returns the amount of records that were read.
+fn stream_json<R, F>(
+    path: &Path,
+    reader: R,
+    options: &ImportOptions,
+    report: &mut ImportReport,
+    sink: &mut F,
+) -> Result<usize>
+where
+    R: Read,
+    F: FnMut(EndStreamWithKind),
+{
+    let is_legacy = path
+        .file_name()
+        .and_then(|x| x.to_str())
+        .is_some_and(LegacyStreamContainer::is_legacy_file_name);
+    let mut record_error = None;
+    let visitor = RecordVisitor {
+        path,
+        is_legacy,
+        options,
+        report,
+        sink,
+        record_error: &mut record_error,
+    };
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let parsed = deserializer
+        .deserialize_seq(visitor)
+        .and_then(|records| deserializer.end().map(|_| records));
+    match (parsed, record_error) {
+        (_, Some(record_error)) => Err(record_error.into()),
+        (Ok(records), None) => Ok(records),
+        (Err(source), None) => Err(Error::Json {
+            path: path.to_path_buf(),
+            source,
+        }),
     }
 }
 
+/// Visits the JSON array of a streaming history file, deserializing and handing over one record at a time.
+struct RecordVisitor<'a, F> {
+    path: &'a Path,
+    is_legacy: bool,
+    options: &'a ImportOptions,
+    report: &'a mut ImportReport,
+    sink: &'a mut F,
+    /// The malformed record that aborted the import, when not lenient.
+    record_error: &'a mut Option<RecordError>,
+}
+
+impl<'de, F> Visitor<'de> for RecordVisitor<'_, F>
+where
+    F: FnMut(EndStreamWithKind),
+{
+    type Value = usize;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a list of streaming history records")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut records = 0;
+        let mut index = 0;
+        while let Some(value) = seq.next_element::<serde_json::Value>()? {
+            let record = if self.is_legacy && LegacyStream::is_podcast(&value) {
+                deserialize_record::<LegacyPodcast>(self.path, index, value)
+                    .map(|x| EndStreamWithKind::from(LegacyStream::Podcast(x)))
+            } else if self.is_legacy {
+                deserialize_record::<LegacyMusic>(self.path, index, value)
+                    .map(|x| EndStreamWithKind::from(LegacyStream::Music(x)))
+            } else {
+                deserialize_record::<EndStream>(self.path, index, value)
+                    .map(EndStreamWithKind::from)
+            };
+            match record {
+                Ok(record) => {
+                    (self.sink)(record);
+                    records += 1;
+                }
+                Err(record_error) if self.options.lenient => {
+                    self.report.quarantined.push(record_error)
+                }
+                Err(record_error) => {
+                    *self.record_error = Some(record_error);
+                    return Err(A::Error::custom("malformed record"));
+                }
+            }
+            index += 1;
+        }
+        Ok(records)
+    }
+}
+
+/// Deserializes a single record, keeping track of where it came from and which field is malformed.
+fn deserialize_record<T>(
+    path: &Path,
+    index: usize,
+    value: serde_json::Value,
+) -> Result<T, RecordError>
+where
+    T: DeserializeOwned,
+{
+    serde_path_to_error::deserialize(value).map_err(|error| {
+        let field = error.path().to_string();
+        RecordError {
+            path: path.to_path_buf(),
+            index,
+            field: (field != ".").then_some(field),
+            message: error.into_inner().to_string(),
+        }
+    })
+}
+
+impl Persist for EndStreamWithKindContainer {}
+
 impl IntoIterator for EndStreamWithKindContainer {
     type Item = EndStreamWithKind;
 
@@ -0,0 +1,337 @@
+//! This module describes how to narrow down the streaming data to the entries matching a search query.
+
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use regex::Regex;
+
+use crate::{Error, Result};
+
+use super::compression::{AssocInfo, CompressedEndStreamWithKindContainer, EndStreamLog};
+
+/// How a search query is compared to an artist, album or track name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchMode {
+    /// Case-insensitive substring match.
+    #[default]
+    Contains,
+    /// The name has to be exactly equal to the query.
+    Exact,
+    /// The query is a regular expression that has to match (part of) the name.
+    Regex,
+}
+
+/// A single compiled search query.
+#[derive(Debug, Clone)]
+pub enum Matcher {
+    Contains(String),
+    Exact(String),
+    Regex(Regex),
+}
+
+impl Matcher {
+    pub fn new(query: &str, mode: MatchMode) -> Result<Self> {
+        Ok(match mode {
+            MatchMode::Contains => Matcher::Contains(query.to_lowercase()),
+            MatchMode::Exact => Matcher::Exact(query.to_string()),
+            MatchMode::Regex => Matcher::Regex(Regex::new(query)?),
+        })
+    }
+
+    pub fn is_match(&self, name: &str) -> bool {
+        match self {
+            Matcher::Contains(query) => name.to_lowercase().contains(query),
+            Matcher::Exact(query) => name == query,
+            Matcher::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+/// A combination of search queries, an entry has to match all of the given queries.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    pub artist: Option<Matcher>,
+    pub album: Option<Matcher>,
+    pub track: Option<Matcher>,
+}
+
+impl Filter {
+    pub fn new(
+        artist: Option<&str>,
+        album: Option<&str>,
+        track: Option<&str>,
+        mode: MatchMode,
+    ) -> Result<Self> {
+        Ok(Self {
+            artist: artist.map(|x| Matcher::new(x, mode)).transpose()?,
+            album: album.map(|x| Matcher::new(x, mode)).transpose()?,
+            track: track.map(|x| Matcher::new(x, mode)).transpose()?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.artist.is_none() && self.album.is_none() && self.track.is_none()
+    }
+
+    pub fn is_match(&self, artist: &str, album: &str, track: &str) -> bool {
+        fn matches(matcher: &Option<Matcher>, name: &str) -> bool {
+            matcher.as_ref().is_none_or(|x| x.is_match(name))
+        }
+        matches(&self.artist, artist) && matches(&self.album, album) && matches(&self.track, track)
+    }
+}
+
+impl CompressedEndStreamWithKindContainer {
+    /// Only keep the entries that match the given filter.
+    pub fn filter(self, filter: &Filter) -> Self {
+        if filter.is_empty() {
+            return self;
+        }
+        self.into_iter()
+            .filter(|(_, _, _, _, artist, album, track, _)| filter.is_match(artist, album, track))
+            .collect()
+    }
+}
+
+/// A range of timestamps, `start` is inclusive and `end` is exclusive, `None` means unbounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DateRange {
+    pub start: Option<NaiveDateTime>,
+    pub end: Option<NaiveDateTime>,
+}
+
+impl DateRange {
+    pub fn new(start: Option<NaiveDateTime>, end: Option<NaiveDateTime>) -> Self {
+        Self { start, end }
+    }
+
+    /// The whole calendar year.
+    pub fn year(year: i32) -> Result<Self> {
+        Ok(Self::new(
+            Some(start_of_month(year, 1)?),
+            Some(start_of_month(year + 1, 1)?),
+        ))
+    }
+
+    /// The whole calendar month, `month` ranges from 1 to 12.
+    pub fn month(year: i32, month: u32) -> Result<Self> {
+        let start = start_of_month(year, month)?;
+        let end = if month == 12 {
+            start_of_month(year + 1, 1)?
+        } else {
+            start_of_month(year, month + 1)?
+        };
+        Ok(Self::new(Some(start), Some(end)))
+    }
+
+    /// The whole quarter, `quarter` ranges from 1 to 4.
+    pub fn quarter(year: i32, quarter: u32) -> Result<Self> {
+        if !(1..=4).contains(&quarter) {
+            return Err(Error::InvalidInput(format!("Invalid quarter: Q{quarter}")));
+        }
+        let start = start_of_month(year, 3 * quarter - 2)?;
+        let end = if quarter == 4 {
+            start_of_month(year + 1, 1)?
+        } else {
+            start_of_month(year, 3 * quarter + 1)?
+        };
+        Ok(Self::new(Some(start), Some(end)))
+    }
+
+    /// The given duration leading up to `now`, failing when its start is before the earliest representable date.
+    pub fn last(duration: Duration, now: NaiveDateTime) -> Result<Self> {
+        let start = now.checked_sub_signed(duration).ok_or_else(|| {
+            Error::InvalidInput(
+                "The period is too long, it starts before the earliest supported date".to_string(),
+            )
+        })?;
+        Ok(Self::new(Some(start), None))
+    }
+
+    /// The range that is contained in both `self` and `other`.
+    pub fn intersect(self, other: Self) -> Self {
+        Self {
+            start: self.start.max(other.start),
+            end: match (self.end, other.end) {
+                (Some(x), Some(y)) => Some(x.min(y)),
+                (x, y) => x.or(y),
+            },
+        }
+    }
+
+    /// A range from `start` up to `end`, failing when it can't contain anything, i.e. when `start` is not before `end`.
+    pub fn between(start: Option<NaiveDateTime>, end: Option<NaiveDateTime>) -> Result<Self> {
+        let range = Self::new(start, end);
+        if let (true, Some(start), Some(end)) = (range.is_empty(), start, end) {
+            return Err(Error::InvalidInput(format!(
+                "The period is empty, {start} is not before {end}"
+            )));
+        }
+        Ok(range)
+    }
+
+    /// Whether no timestamp at all is contained.
+    pub fn is_empty(&self) -> bool {
+        matches!((self.start, self.end), (Some(start), Some(end)) if start >= end)
+    }
+
+    pub fn is_unbounded(&self) -> bool {
+        self.start.is_none() && self.end.is_none()
+    }
+
+    pub fn contains(&self, timestamp: &NaiveDateTime) -> bool {
+        self.start.is_none_or(|x| x <= *timestamp) && self.end.is_none_or(|x| *timestamp < x)
+    }
+}
+
+fn start_of_month(year: i32, month: u32) -> Result<NaiveDateTime> {
+    NaiveDate::from_ymd_opt(year, month, 1)
+        .and_then(|x| x.and_hms_opt(0, 0, 0))
+        .ok_or_else(|| Error::InvalidInput(format!("Invalid month: {year}-{month:02}")))
+}
+
+impl AssocInfo {
+    /// Only keep the streams within the given range, the total play time is recomputed from the remaining log.
+    ///
+    /// Returns `None` when no streams remain.
+    pub fn filter_dates(self, range: &DateRange) -> Option<Self> {
+        let end_stream_log: EndStreamLog = self
+            .end_stream_log
+            .into_iter()
+            .filter(|(ts, _)| range.contains(ts))
+            .collect();
+        if end_stream_log.is_empty() {
+            return None;
+        }
+        Some(Self {
+            total_ms_played: end_stream_log
+                .iter()
+                .fold(Duration::zero(), |acc, (_, x)| acc + x.ms_played),
+            end_stream_log,
+            ..self
+        })
+    }
+}
+
+impl CompressedEndStreamWithKindContainer {
+    /// Only keep the streams within the given range, dropping tracks that have no streams left.
+    pub fn filter_dates(self, range: &DateRange) -> Self {
+        if range.is_unbounded() {
+            return self;
+        }
+        self.into_iter()
+            .filter_map(
+                |(username, conn_country, platform, kind, artist, album, track, info)| {
+                    info.filter_dates(range).map(|info| {
+                        (
+                            username,
+                            conn_country,
+                            platform,
+                            kind,
+                            artist,
+                            album,
+                            track,
+                            info,
+                        )
+                    })
+                },
+            )
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timestamp(year: i32, month: u32, day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(year, month, day)
+            .and_then(|x| x.and_hms_opt(hour, 0, 0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_match_modes() -> Result<()> {
+        let contains = Matcher::new("BEYON", MatchMode::Contains)?;
+        assert!(contains.is_match("Beyoncé"));
+        assert!(contains.is_match("Destiny's Child & beyoncé"));
+        assert!(!contains.is_match("Bey"));
+
+        let exact = Matcher::new("Beyoncé", MatchMode::Exact)?;
+        assert!(exact.is_match("Beyoncé"));
+        assert!(!exact.is_match("beyoncé"));
+        assert!(!exact.is_match("Beyoncé "));
+
+        let regex = Matcher::new("^(?i)the (xx|1975)$", MatchMode::Regex)?;
+        assert!(regex.is_match("The xx"));
+        assert!(regex.is_match("the 1975"));
+        assert!(!regex.is_match("The xx Remixed"));
+        assert!(Matcher::new("(", MatchMode::Regex).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_has_to_match_every_query() -> Result<()> {
+        let filter = Filter::new(Some("daft"), None, Some("one more"), MatchMode::Contains)?;
+        assert!(filter.is_match("Daft Punk", "Discovery", "One More Time"));
+        assert!(!filter.is_match("Daft Punk", "Discovery", "Digital Love"));
+        assert!(!filter.is_match("Stardust", "Discovery", "One More Time"));
+        assert!(Filter::new(None, None, None, MatchMode::Exact)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_calendar_ranges_include_start_and_exclude_end() -> Result<()> {
+        let year = DateRange::year(2023)?;
+        assert!(year.contains(&timestamp(2023, 1, 1, 0)));
+        assert!(year.contains(&timestamp(2023, 12, 31, 23)));
+        assert!(!year.contains(&timestamp(2024, 1, 1, 0)));
+        assert!(!year.contains(&timestamp(2022, 12, 31, 23)));
+
+        let december = DateRange::month(2023, 12)?;
+        assert_eq!(december.end, Some(timestamp(2024, 1, 1, 0)));
+        let quarter = DateRange::quarter(2023, 3)?;
+        assert_eq!(
+            (quarter.start, quarter.end),
+            (
+                Some(timestamp(2023, 7, 1, 0)),
+                Some(timestamp(2023, 10, 1, 0))
+            )
+        );
+        assert!(DateRange::quarter(2023, 5).is_err());
+        assert!(DateRange::month(2023, 13).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_last_is_measured_back_from_now() -> Result<()> {
+        let now = timestamp(2023, 6, 15, 12);
+        let range = DateRange::last(Duration::days(7), now)?;
+        assert_eq!(range.start, Some(timestamp(2023, 6, 8, 12)));
+        assert_eq!(range.end, None);
+        assert!(range.contains(&now));
+        assert!(DateRange::last(Duration::days(200_000_000), now).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_between_rejects_empty_ranges() {
+        let (early, late) = (timestamp(2023, 1, 1, 0), timestamp(2023, 2, 1, 0));
+        assert!(DateRange::between(Some(early), Some(late)).is_ok());
+        assert!(DateRange::between(Some(early), None).is_ok());
+        assert!(DateRange::between(Some(late), Some(early)).is_err());
+        assert!(DateRange::between(Some(early), Some(early)).is_err());
+    }
+
+    #[test]
+    fn test_intersect_keeps_the_overlap() -> Result<()> {
+        let range = DateRange::year(2023)?.intersect(DateRange::new(
+            Some(timestamp(2023, 6, 1, 0)),
+            Some(timestamp(2024, 6, 1, 0)),
+        ));
+        assert_eq!(range.start, Some(timestamp(2023, 6, 1, 0)));
+        assert_eq!(range.end, Some(timestamp(2024, 1, 1, 0)));
+        assert!(DateRange::year(2022)?
+            .intersect(DateRange::year(2023)?)
+            .is_empty());
+        Ok(())
+    }
+}
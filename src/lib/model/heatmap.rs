@@ -0,0 +1,90 @@
+//! This module describes when the streaming happened, i.e. the listening time per weekday and hour of the day.
+
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, Duration, DurationRound, NaiveDateTime, Timelike};
+use serde::Serialize;
+
+use super::compression::CompressedEndStreamWithKindContainer;
+
+pub const WEEKDAYS: usize = 7;
+pub const HOURS: usize = 24;
+
+/// How the streaming data is split into separate heatmaps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeatmapSplit {
+    /// One heatmap per artist or podcast.
+    Artist,
+    /// One heatmap per `EndStreamKind`.
+    Kind,
+}
+
+/// The listening time in milliseconds per weekday, starting at Monday, and hour of the day.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Heatmap {
+    /// What the streams have in common, e.g. the artist, or `All`.
+    pub label: String,
+    pub ms_played: [[i64; HOURS]; WEEKDAYS],
+}
+
+impl Heatmap {
+    pub fn new(label: String) -> Self {
+        Self {
+            label,
+            ms_played: [[0; HOURS]; WEEKDAYS],
+        }
+    }
+
+    /// Adds the time between the start and end of a stream, split over the hours it spans.
+    pub fn add(&mut self, start: NaiveDateTime, end: NaiveDateTime) {
+        let mut current = start;
+        while current < end {
+            let next_hour = current
+                .duration_trunc(Duration::hours(1))
+                .map_or(end, |x| x + Duration::hours(1));
+            let until = next_hour.min(end);
+            let weekday = current.weekday().num_days_from_monday() as usize;
+            self.ms_played[weekday][current.hour() as usize] +=
+                (until - current).num_milliseconds();
+            current = until;
+        }
+    }
+
+    /// Total listening time.
+    pub fn total_ms_played(&self) -> i64 {
+        self.ms_played.iter().flatten().sum()
+    }
+
+    /// The listening time of the busiest hour.
+    pub fn max_ms_played(&self) -> i64 {
+        self.ms_played.iter().flatten().copied().max().unwrap_or(0)
+    }
+}
+
+/// Represents a collection of heatmaps, from most to least listened.
+#[repr(transparent)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HeatmapContainer(pub Vec<Heatmap>);
+
+impl HeatmapContainer {
+    /// Buckets every stream by the weekday and hour it was played, in a single heatmap or split per artist or kind.
+    pub fn new(value: CompressedEndStreamWithKindContainer, split: Option<HeatmapSplit>) -> Self {
+        let mut acc: BTreeMap<String, Heatmap> = BTreeMap::new();
+        for (_, _, _, kind, artist, _album, _track, info) in value {
+            let label = match split {
+                None => "All".to_string(),
+                Some(HeatmapSplit::Artist) => artist,
+                Some(HeatmapSplit::Kind) => kind.to_string(),
+            };
+            let heatmap = acc
+                .entry(label.clone())
+                .or_insert_with(|| Heatmap::new(label));
+            for (ts, entry) in info.end_stream_log.iter() {
+                heatmap.add(*ts - entry.ms_played, *ts);
+            }
+        }
+        let mut out: Vec<Heatmap> = acc.into_values().collect();
+        out.sort_by_key(|x| std::cmp::Reverse(x.total_ms_played()));
+        Self(out)
+    }
+}
@@ -0,0 +1,132 @@
+//! This module describes how the streaming history files are discovered, and what is reported back after importing them.
+
+use std::{
+    fmt::Display,
+    fs::read_dir,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::Result;
+
+use super::end_stream::is_streaming_history_file_name;
+
+/// Options that influence how the streaming history files are imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ImportOptions {
+    /// Skip malformed records and report them, instead of failing the whole import.
+    pub lenient: bool,
+    /// Parse the files on multiple threads, the result is identical to parsing them one after another.
+    pub parallel: bool,
+}
+
+/// A single record that could not be read.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub struct RecordError {
+    /// The file, or archive member, containing the record.
+    pub path: PathBuf,
+    /// The index of the record in the JSON array.
+    pub index: usize,
+    /// The offending field, `None` when the record as a whole is malformed, e.g. a missing field.
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl Display for RecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}[{}]: ", self.path.display(), self.index)?;
+        if let Some(field) = &self.field {
+            write!(f, "field `{field}`: ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// A file, or archive member, that contributed records to an import.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedFile {
+    pub path: PathBuf,
+    pub records: usize,
+}
+
+/// Summary of an import: which files contributed how many records, and which files were skipped.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ImportReport {
+    pub files: Vec<ImportedFile>,
+    pub skipped: Vec<PathBuf>,
+    /// The records that were skipped in lenient mode.
+    pub quarantined: Vec<RecordError>,
+}
+
+impl ImportReport {
+    pub fn total_records(&self) -> usize {
+        self.files.iter().map(|x| x.records).sum()
+    }
+
+    /// Moves everything reported in `other` after the things reported in `self`.
+    pub fn append(&mut self, mut other: Self) {
+        self.files.append(&mut other.files);
+        self.skipped.append(&mut other.skipped);
+        self.quarantined.append(&mut other.quarantined);
+    }
+}
+
+impl Display for ImportReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for skipped in &self.skipped {
+            writeln!(f, "warning: skipped unknown file `{}`", skipped.display())?;
+        }
+        writeln!(
+            f,
+            "Imported {} records from {} files:",
+            self.total_records(),
+            self.files.len()
+        )?;
+        for file in &self.files {
+            writeln!(f, "  {:>8}  {}", file.records, file.path.display())?;
+        }
+        if !self.quarantined.is_empty() {
+            writeln!(
+                f,
+                "Quarantined {} malformed records:",
+                self.quarantined.len()
+            )?;
+            for record_error in &self.quarantined {
+                writeln!(f, "  {record_error}")?;
+            }
+        }
+        std::fmt::Result::Ok(())
+    }
+}
+
+/// Recursively finds the streaming history files in the folder, sorted by path.
+///
+/// Returns the matching files, and all other files that were skipped.
+pub fn find_streaming_history_files<P>(folder: P) -> Result<(Vec<PathBuf>, Vec<PathBuf>)>
+where
+    P: AsRef<Path>,
+{
+    let mut found = Vec::new();
+    let mut skipped = Vec::new();
+    let mut pending = vec![folder.as_ref().to_path_buf()];
+    while let Some(directory) = pending.pop() {
+        for maybe_entry in read_dir(&directory)? {
+            let path = maybe_entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path
+                .file_name()
+                .and_then(|x| x.to_str())
+                .is_some_and(is_streaming_history_file_name)
+            {
+                found.push(path);
+            } else {
+                skipped.push(path);
+            }
+        }
+    }
+    found.sort();
+    skipped.sort();
+    Ok((found, skipped))
+}
@@ -0,0 +1,166 @@
+//! This module describes the format of the quicker "Account data" export, i.e. the `StreamingHistory*.json` files.
+//!
+//! These records contain far less information than the Extended Streaming History, the missing fields are left empty.
+
+use chrono::{Duration, NaiveDateTime};
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+use crate::serde::{
+    deserialization::{duration_deserialization, legacy_date_time_deserialization},
+    serialization::{duration_serialization, legacy_date_time_serialization},
+};
+
+use super::end_stream::{EndStream, EndStreamKind, EndStreamWithKind};
+
+/// The file name prefix of the account data streaming history, e.g. `StreamingHistory_music_0.json`.
+pub const LEGACY_FILE_PREFIX: &str = "StreamingHistory";
+
+/// A song in the account data export, i.e. a `StreamingHistory_music_*.json` record.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LegacyMusic {
+    /// Timestamp indicating when the track stopped playing in UTC, only precise to the minute.
+    #[serde(
+        deserialize_with = "legacy_date_time_deserialization",
+        serialize_with = "legacy_date_time_serialization"
+    )]
+    pub end_time: NaiveDateTime,
+    /// Name of the artist.
+    pub artist_name: String,
+    /// Name of the track.
+    pub track_name: String,
+    /// The number of milliseconds the stream was played.
+    #[serde(
+        deserialize_with = "duration_deserialization",
+        serialize_with = "duration_serialization"
+    )]
+    pub ms_played: Duration,
+}
+
+/// A podcast episode in the account data export, i.e. a `StreamingHistory_podcast_*.json` record.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LegacyPodcast {
+    /// Timestamp indicating when the episode stopped playing in UTC, only precise to the minute.
+    #[serde(
+        deserialize_with = "legacy_date_time_deserialization",
+        serialize_with = "legacy_date_time_serialization"
+    )]
+    pub end_time: NaiveDateTime,
+    /// Name of the podcast.
+    pub podcast_name: String,
+    /// Name of the episode.
+    pub episode_name: String,
+    /// The number of milliseconds the stream was played.
+    #[serde(
+        deserialize_with = "duration_deserialization",
+        serialize_with = "duration_serialization"
+    )]
+    pub ms_played: Duration,
+}
+
+/// Represents a singular record of the account data export, either a song or a podcast episode.
+#[derive(Debug, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+#[serde(untagged)]
+pub enum LegacyStream {
+    Music(LegacyMusic),
+    Podcast(LegacyPodcast),
+}
+
+impl LegacyStream {
+    /// Whether the record is a podcast episode, judging by its keys.
+    ///
+    /// Picking the format up front, instead of trying both, keeps the errors of the record specific to its fields.
+    pub fn is_podcast(value: &serde_json::Value) -> bool {
+        value.get("episodeName").is_some() || value.get("podcastName").is_some()
+    }
+}
+
+impl<'de> Deserialize<'de> for LegacyStream {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        if Self::is_podcast(&value) {
+            LegacyPodcast::deserialize(value).map(Self::Podcast)
+        } else {
+            LegacyMusic::deserialize(value).map(Self::Music)
+        }
+        .map_err(de::Error::custom)
+    }
+}
+
+/// Represents a collection of LegacyStream, i.e. the content of one `StreamingHistory*.json` file.
+#[repr(transparent)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub struct LegacyStreamContainer(pub Vec<LegacyStream>);
+
+impl LegacyStreamContainer {
+    /// Whether the file name belongs to the account data export.
+    pub fn is_legacy_file_name(file_name: &str) -> bool {
+        file_name.starts_with(LEGACY_FILE_PREFIX) && file_name.ends_with(".json")
+    }
+}
+
+impl IntoIterator for LegacyStreamContainer {
+    type Item = LegacyStream;
+
+    type IntoIter = <Vec<LegacyStream> as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl FromIterator<LegacyStream> for LegacyStreamContainer {
+    fn from_iter<T: IntoIterator<Item = LegacyStream>>(iter: T) -> Self {
+        Self(Vec::from_iter(iter))
+    }
+}
+
+/// Convert a `LegacyStream` into `EndStreamWithKind`, leaving the fields the account data lacks empty.
+impl From<LegacyStream> for EndStreamWithKind {
+    fn from(value: LegacyStream) -> Self {
+        let (kind, ts, ms_played) = match &value {
+            LegacyStream::Music(x) => (EndStreamKind::EndSong, x.end_time, x.ms_played),
+            LegacyStream::Podcast(x) => (EndStreamKind::EndEpisode, x.end_time, x.ms_played),
+        };
+        let mut end_stream = EndStream {
+            ts,
+            username: String::new(),
+            platform: String::new(),
+            ms_played,
+            conn_country: String::new(),
+            ip_addr_decrypted: None,
+            user_agent_decrypted: None,
+            master_metadata_track_name: None,
+            master_metadata_album_artist_name: None,
+            master_metadata_album_album_name: None,
+            spotify_track_uri: None,
+            episode_name: None,
+            episode_show_name: None,
+            spotify_episode_uri: None,
+            reason_start: None,
+            reason_end: None,
+            shuffle: None,
+            skipped: None,
+            offline: None,
+            offline_timestamp: None,
+            incognito_mode: None,
+        };
+        match value {
+            LegacyStream::Music(x) => {
+                end_stream.master_metadata_album_artist_name = Some(x.artist_name);
+                end_stream.master_metadata_album_album_name = Some(String::new());
+                end_stream.master_metadata_track_name = Some(x.track_name);
+            }
+            LegacyStream::Podcast(x) => {
+                end_stream.master_metadata_album_artist_name = Some(x.podcast_name.clone());
+                end_stream.episode_show_name = Some(x.podcast_name);
+                end_stream.episode_name = Some(x.episode_name);
+            }
+        }
+        Self { kind, end_stream }
+    }
+}
@@ -0,0 +1,132 @@
+//! This module describes how a new export is merged into already imported streaming data.
+//!
+//! Exports overlap, so every stream is identified by its username, timestamp and names.
+
+use std::{collections::HashMap, fmt::Display};
+
+use chrono::NaiveDateTime;
+
+use super::{
+    compression::{CompressedEndStreamWithKindContainer, EndStreamLogEntry},
+    end_stream::{EndStreamKind, EndStreamWithKind, EndStreamWithKindContainer},
+};
+
+/// Identifies a single stream at a given timestamp: username and the artist, album and track names.
+///
+/// A track can have several URIs with the same names, e.g. an explicit and a clean version, and these share a single `AssocInfo` that only keeps the first URI, so the names are used instead.
+type StreamKey<'a> = (&'a str, (&'a str, &'a str, &'a str));
+
+/// Everything else that is known about a stream, used to detect conflicting records.
+type StreamValue<'a> = (&'a str, &'a str, &'a EndStreamKind, &'a EndStreamLogEntry);
+
+/// Summary of a merge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MergeReport {
+    /// Records that were not yet present, and have been added.
+    pub new: usize,
+    /// Records that were already present with the exact same information.
+    pub duplicate: usize,
+    /// Records that were already present with different information, the existing record is kept.
+    pub conflicting: usize,
+    /// Records without artist, album or track name, these can't be stored.
+    pub incomplete: usize,
+}
+
+impl Display for MergeReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Merged: {} new, {} duplicate, {} conflicting, {} incomplete records.",
+            self.new, self.duplicate, self.conflicting, self.incomplete
+        )
+    }
+}
+
+impl CompressedEndStreamWithKindContainer {
+    /// The existing streams by timestamp, borrowed from `self` so nothing is cloned.
+    fn index(&self) -> HashMap<NaiveDateTime, Vec<(StreamKey<'_>, StreamValue<'_>)>> {
+        let mut index: HashMap<_, Vec<_>> = HashMap::new();
+        for (username, countries) in &self.0 {
+            for (conn_country, platforms) in countries {
+                for (platform, kinds) in platforms {
+                    for (kind, artists) in kinds {
+                        for (artist, albums) in artists {
+                            for (album, tracks) in albums {
+                                for (track, info) in tracks {
+                                    let key = (
+                                        username.as_str(),
+                                        (artist.as_str(), album.as_str(), track.as_str()),
+                                    );
+                                    for (ts, entry) in info.end_stream_log.iter() {
+                                        index.entry(*ts).or_default().push((
+                                            key,
+                                            (conn_country.as_str(), platform.as_str(), kind, entry),
+                                        ));
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        index
+    }
+
+    /// Merges the records into `self`, skipping records that are already present.
+    ///
+    /// Every existing stream can only be matched once, so streams of the same track ending in the same second are all kept.
+    pub fn merge(&mut self, records: EndStreamWithKindContainer) -> MergeReport {
+        self.merge_stream(|sink| records.into_iter().for_each(sink))
+            .1
+    }
+
+    /// Like `merge`, but `read` passes every record to the sink as soon as it is read, e.g. with `stream_folder` or `stream_zip`.
+    ///
+    /// Only the new records are kept in memory until they are inserted at the end, so importing an export that mostly overlaps takes little memory.
+    pub fn merge_stream<F, T>(&mut self, read: F) -> (T, MergeReport)
+    where
+        F: FnOnce(&mut dyn FnMut(EndStreamWithKind)) -> T,
+    {
+        let mut report = MergeReport::default();
+        let mut new = Vec::new();
+        let out = {
+            let mut index = self.index();
+            read(&mut |record| {
+                let Some(names) = record.names() else {
+                    report.incomplete += 1;
+                    return;
+                };
+                let key = (
+                    record.end_stream.username.as_str(),
+                    (names.0.as_str(), names.1.as_str(), names.2.as_str()),
+                );
+                let Some(streams) = index.get_mut(&record.end_stream.ts) else {
+                    new.push(record);
+                    return;
+                };
+                let entry = EndStreamLogEntry::from(&record);
+                let value: StreamValue = (
+                    &record.end_stream.conn_country,
+                    &record.end_stream.platform,
+                    &record.kind,
+                    &entry,
+                );
+                if let Some(position) = streams.iter().position(|x| *x == (key, value)) {
+                    streams.swap_remove(position);
+                    report.duplicate += 1;
+                } else if let Some(position) = streams.iter().rposition(|x| x.0 == key) {
+                    streams.swap_remove(position);
+                    report.conflicting += 1;
+                } else {
+                    new.push(record);
+                }
+            })
+        };
+        report.new = new.len();
+        for record in new {
+            self.insert_end_stream(record);
+        }
+        (out, report)
+    }
+}
@@ -0,0 +1,124 @@
+//! This module describes how persistent files of older format versions are converted into the current structures.
+//!
+//! # Format versions of `CompressedEndStreamWithKindContainer`
+//!
+//! 0. No header, the log holds either a single entry per timestamp, or a list of entries per timestamp.
+//! 1. Never written with a header, the single entry per timestamp layout only exists headerless as version 0.
+//! 2. The log holds a list of entries per timestamp.
+//!
+//! # Format versions of `Cache`
+//!
+//! 0. and 2. Only the `CompressedEndStreamWithKindContainer`, the exports it was built from are unknown.
+//! 3. The `CompressedEndStreamWithKindContainer` together with the fingerprints of the exports it was built from.
+
+use std::collections::BTreeMap;
+
+use chrono::{Duration, NaiveDateTime};
+use rmp_serde::decode::from_slice;
+use serde::Deserialize;
+
+use crate::{serde::deserialization::duration_deserialization, Result};
+
+use super::{
+    cache::Cache,
+    compression::{
+        AssocInfo, CompressedEndStreamWithKindContainer, EndStreamLog, EndStreamLogEntry,
+    },
+    end_stream::EndStreamKind,
+    FormatError, Persist,
+};
+
+/// The headerless layout of `AssocInfo`, its log holds a single entry per timestamp.
+#[derive(Debug, Deserialize)]
+struct AssocInfoV1 {
+    #[serde(deserialize_with = "duration_deserialization")]
+    total_ms_played: Duration,
+    spotify_track_uri: Option<String>,
+    spotify_episode_uri: Option<String>,
+    end_stream_log: BTreeMap<NaiveDateTime, EndStreamLogEntry>,
+}
+
+/// The headerless layout of `ArtistMap` with a single entry per timestamp.
+type ArtistMapV1 = BTreeMap<String, BTreeMap<String, BTreeMap<String, AssocInfoV1>>>;
+
+/// The headerless layout of `PlatformMap` with a single entry per timestamp.
+type PlatformMapV1 = BTreeMap<String, BTreeMap<EndStreamKind, ArtistMapV1>>;
+
+/// The headerless layout of `CompressedEndStreamWithKindContainer` with a single entry per timestamp.
+#[derive(Debug, Deserialize)]
+struct CompressedEndStreamWithKindContainerV1(BTreeMap<String, BTreeMap<String, PlatformMapV1>>);
+
+impl From<AssocInfoV1> for AssocInfo {
+    fn from(value: AssocInfoV1) -> Self {
+        AssocInfo {
+            total_ms_played: value.total_ms_played,
+            spotify_track_uri: value.spotify_track_uri,
+            spotify_episode_uri: value.spotify_episode_uri,
+            end_stream_log: EndStreamLog::from_iter(value.end_stream_log),
+        }
+    }
+}
+
+impl From<CompressedEndStreamWithKindContainerV1> for CompressedEndStreamWithKindContainer {
+    fn from(value: CompressedEndStreamWithKindContainerV1) -> Self {
+        let mut out = Self::default();
+        for (username, countries) in value.0 {
+            for (conn_country, platforms) in countries {
+                for (platform, kinds) in platforms {
+                    for (kind, artists) in kinds {
+                        for (artist, albums) in artists {
+                            for (album, tracks) in albums {
+                                for (track, info) in tracks {
+                                    out.0
+                                        .entry(username.clone())
+                                        .or_default()
+                                        .entry(conn_country.clone())
+                                        .or_default()
+                                        .entry(platform.clone())
+                                        .or_default()
+                                        .entry(kind.clone())
+                                        .or_default()
+                                        .entry(artist.clone())
+                                        .or_default()
+                                        .entry(album.clone())
+                                        .or_default()
+                                        .insert(track, AssocInfo::from(info));
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+impl Persist for CompressedEndStreamWithKindContainer {
+    const FORMAT_VERSION: u16 = 2;
+
+    fn migrate(version: u16, bytes: &[u8]) -> Result<Self> {
+        match version {
+            0 => from_slice::<CompressedEndStreamWithKindContainerV1>(bytes)
+                .map(Self::from)
+                .or_else(|_| Self::_impl_deserialize(bytes)),
+            2 => Self::_impl_deserialize(bytes),
+            _ => Err(FormatError::UnsupportedVersion(version).into()),
+        }
+    }
+}
+
+impl Persist for Cache {
+    const FORMAT_VERSION: u16 = 3;
+
+    fn migrate(version: u16, bytes: &[u8]) -> Result<Self> {
+        match version {
+            0 | 2 => Ok(Self {
+                sources: Vec::new(),
+                data: CompressedEndStreamWithKindContainer::migrate(version, bytes)?,
+            }),
+            3 => Self::_impl_deserialize(bytes),
+            _ => Err(FormatError::UnsupportedVersion(version).into()),
+        }
+    }
+}
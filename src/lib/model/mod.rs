@@ -1,21 +1,148 @@
+pub mod aggregate;
+pub mod cache;
+pub mod codec;
 pub mod compression;
 pub mod end_stream;
+pub mod filter;
+pub mod heatmap;
+pub mod import;
+pub mod legacy;
+pub mod merge;
+pub mod migration;
+pub mod session;
+pub mod timezone;
+pub mod wrapped;
 
 use std::{
-    fs::File,
+    fs::{copy, remove_file, rename, File},
     io::{Read, Write},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
-use eyre::{Ok, Result};
-use flate2::{
-    write::{DeflateDecoder, DeflateEncoder},
-    Compression,
-};
 use rmp_serde::{decode::from_slice, encode::to_vec};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::Result;
+
+use self::codec::Codec;
+
+/// Every persistent file starts with these bytes.
+pub const MAGIC: [u8; 4] = *b"SPST";
+
+/// The size of the header: magic, format version, codec, codec level and the checksum.
+pub const HEADER_LEN: usize = 12;
+
+/// Errors in the header of a persistent file.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum FormatError {
+    #[error("the file is too short to contain a header")]
+    Truncated,
+    #[error("the file was written by a newer version of this tool (format version {found}, this version supports up to {supported})")]
+    NewerVersion { found: u16, supported: u16 },
+    #[error("format version {0} is not supported")]
+    UnsupportedVersion(u16),
+    #[error("unknown compression method `{0}`")]
+    UnknownCompression(u8),
+    #[error(
+        "checksum mismatch, the file is corrupt (expected {expected:#010x}, found {found:#010x})"
+    )]
+    ChecksumMismatch { expected: u32, found: u32 },
+}
+
+/// The self-describing header in front of every persistent file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    /// The format version of the payload, see [`Persist::FORMAT_VERSION`].
+    pub version: u16,
+    /// How the payload is compressed.
+    pub codec: Codec,
+    /// CRC-32 of the payload, as stored.
+    pub checksum: u32,
+}
+
+impl Header {
+    pub fn to_bytes(self) -> [u8; HEADER_LEN] {
+        let mut out = [0; HEADER_LEN];
+        out[0..4].copy_from_slice(&MAGIC);
+        out[4..6].copy_from_slice(&self.version.to_le_bytes());
+        out[6..8].copy_from_slice(&self.codec.to_header_bytes());
+        out[8..12].copy_from_slice(&self.checksum.to_le_bytes());
+        out
+    }
+
+    /// Splits the bytes into a header and the payload, returns `None` when the magic is missing, i.e. a file from before headers were introduced.
+    pub fn split(bytes: &[u8]) -> Result<Option<(Self, &[u8])>> {
+        if !bytes.starts_with(&MAGIC) {
+            return Ok(None);
+        }
+        if bytes.len() < HEADER_LEN {
+            return Err(FormatError::Truncated.into());
+        }
+        let (header, payload) = bytes.split_at(HEADER_LEN);
+        let header = Header {
+            version: u16::from_le_bytes([header[4], header[5]]),
+            codec: Codec::from_header_bytes([header[6], header[7]])?,
+            checksum: u32::from_le_bytes([header[8], header[9], header[10], header[11]]),
+        };
+        let found = crc32fast::hash(payload);
+        if found != header.checksum {
+            return Err(FormatError::ChecksumMismatch {
+                expected: header.checksum,
+                found,
+            }
+            .into());
+        }
+        Ok(Some((header, payload)))
+    }
+}
+
+/// Appends an extension to the file name, e.g. `default.bin` becomes `default.bin.bak`.
+fn append_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".");
+    file_name.push(extension);
+    path.with_file_name(file_name)
+}
+
+/// Where the previous version of a persistent file is kept, see [`Persist::save_to_file_with_backup`].
+pub fn backup_path(path: &Path) -> PathBuf {
+    append_extension(path, "bak")
+}
+
+/// Writes to a temporary file, flushes it to disk, and renames it to `path`, so `path` is never left half written.
+fn write_atomically(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let temp_path = append_extension(path, "tmp");
+    let written = File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(bytes)?;
+            file.sync_all()
+        })
+        .and_then(|_| rename(&temp_path, path));
+    if written.is_err() {
+        let _ = remove_file(&temp_path);
+    }
+    written?;
+    // Make sure the rename itself survives a crash.
+    #[cfg(unix)]
+    if let Some(parent) = path.parent().filter(|x| !x.as_os_str().is_empty()) {
+        File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
 
 pub trait Persist: Serialize + for<'a> Deserialize<'a> {
+    /// The version of the persisted structure of `Self`, bump this whenever it changes and add a migration.
+    const FORMAT_VERSION: u16 = 1;
+
+    /// Converts a serialized payload of an older format version into `Self`.
+    ///
+    /// Version `0` is a file from before headers were introduced.
+    fn migrate(version: u16, bytes: &[u8]) -> Result<Self> {
+        let _ = version;
+        Self::_impl_deserialize(bytes)
+    }
+
     fn _impl_serialize(&self) -> Result<Vec<u8>> {
         Ok(to_vec(self)?)
     }
@@ -24,45 +151,65 @@ pub trait Persist: Serialize + for<'a> Deserialize<'a> {
         Ok(from_slice(bytes)?)
     }
 
-    fn _impl_compress(bytes: &[u8]) -> Result<Vec<u8>> {
-        let mut encoder = DeflateEncoder::new(Vec::with_capacity(128), Compression::best());
-        encoder.write_all(bytes)?;
-        Ok(encoder.finish()?)
-    }
-
-    fn _impl_decompress(compressed_bytes: &[u8]) -> Result<Vec<u8>> {
-        let mut decoder = DeflateDecoder::new(Vec::with_capacity(128));
-        decoder.write_all(compressed_bytes)?;
-        Ok(decoder.finish()?)
-    }
-
-    fn to_bytes(&self, compress: bool) -> Result<Vec<u8>> {
-        let bytes = self._impl_serialize()?;
-        if compress {
-            Self::_impl_compress(&bytes)
-        } else {
-            Ok(bytes)
-        }
+    fn to_bytes(&self, codec: Codec) -> Result<Vec<u8>> {
+        let payload = codec.compress(&self._impl_serialize()?)?;
+        let header = Header {
+            version: Self::FORMAT_VERSION,
+            codec,
+            checksum: crc32fast::hash(&payload),
+        };
+        let mut out = Vec::with_capacity(HEADER_LEN + payload.len());
+        out.extend_from_slice(&header.to_bytes());
+        out.extend_from_slice(&payload);
+        Ok(out)
     }
 
     fn from_bytes(bytes: &[u8]) -> Result<Self> {
-        if let eyre::Result::Ok(decompressed_bytes) = Self::_impl_decompress(bytes) {
+        let Some((header, payload)) = Header::split(bytes)? else {
+            // Files from before headers were introduced, we have to guess whether they are deflated.
+            return if let Ok(decompressed_bytes) = Codec::Deflate.decompress(bytes) {
+                Self::migrate(0, &decompressed_bytes)
+            } else {
+                Self::migrate(0, bytes)
+            };
+        };
+        if header.version > Self::FORMAT_VERSION {
+            return Err(FormatError::NewerVersion {
+                found: header.version,
+                supported: Self::FORMAT_VERSION,
+            }
+            .into());
+        }
+        let decompressed_bytes = header.codec.decompress(payload)?;
+        if header.version == Self::FORMAT_VERSION {
             Self::_impl_deserialize(&decompressed_bytes)
         } else {
-            Self::_impl_deserialize(bytes)
+            Self::migrate(header.version, &decompressed_bytes)
         }
     }
 
-    fn save_to_file<P>(&self, path: P, compress: bool) -> Result<()>
+    /// Saves atomically: the bytes are written to a temporary file next to `path`, flushed to disk and only then renamed to `path`.
+    fn save_to_file<P>(&self, path: P, codec: Codec) -> Result<()>
     where
         P: AsRef<Path>,
     {
-        let maybe_compressed_bytes = self.to_bytes(compress)?;
-        let mut file_handle = File::create(path)?;
-        file_handle.write_all(&maybe_compressed_bytes)?;
+        let maybe_compressed_bytes = self.to_bytes(codec)?;
+        write_atomically(path.as_ref(), &maybe_compressed_bytes)?;
         Ok(())
     }
 
+    /// Like `save_to_file`, but keeps the previous file, if any, at its [`backup_path`].
+    fn save_to_file_with_backup<P>(&self, path: P, codec: Codec) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if path.exists() {
+            copy(path, backup_path(path))?;
+        }
+        self.save_to_file(path, codec)
+    }
+
     fn load_from_file<P>(path: P) -> Result<Self>
     where
         P: AsRef<Path>,
@@ -73,5 +220,3 @@ pub trait Persist: Serialize + for<'a> Deserialize<'a> {
         Self::from_bytes(&maybe_compressed_bytes)
     }
 }
-
-impl<T: Serialize + for<'a> Deserialize<'a>> Persist for T {}
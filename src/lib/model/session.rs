@@ -0,0 +1,171 @@
+//! This module describes listening sessions, i.e. streams that follow each other without a long pause in between.
+
+use std::collections::BTreeMap;
+
+use chrono::{Duration, NaiveDateTime};
+
+use super::{compression::CompressedEndStreamWithKindContainer, end_stream::EndStreamKind};
+
+/// A single stream placed on the timeline, the start is reconstructed as `ts - ms_played`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Play {
+    pub start: NaiveDateTime,
+    /// The `ts` of the stream.
+    pub end: NaiveDateTime,
+    pub username: String,
+    pub platform: String,
+    pub kind: EndStreamKind,
+    pub artist_or_podcast: String,
+    pub track_or_episode: String,
+}
+
+/// Consecutive streams of a single user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub username: String,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    /// The platform the most time was spent on during the session.
+    pub platform: String,
+    pub plays: Vec<Play>,
+}
+
+impl Session {
+    fn new(play: Play) -> Self {
+        Self {
+            username: play.username.clone(),
+            start: play.start,
+            end: play.end,
+            platform: String::new(),
+            plays: vec![play],
+        }
+    }
+
+    /// The time between the start of the first and the end of the last stream.
+    pub fn length(&self) -> Duration {
+        self.end - self.start
+    }
+
+    /// Picks the platform the most time was spent on, ties go to the first in lexicographical order.
+    fn finish(mut self) -> Self {
+        let mut per_platform: BTreeMap<&str, Duration> = BTreeMap::new();
+        for play in &self.plays {
+            *per_platform
+                .entry(&play.platform)
+                .or_insert_with(Duration::zero) += play.end - play.start;
+        }
+        let mut dominant: Option<(&str, Duration)> = None;
+        for (platform, time) in per_platform {
+            if dominant.is_none_or(|(_, x)| time > x) {
+                dominant = Some((platform, time));
+            }
+        }
+        self.platform = dominant.map(|(x, _)| x.to_string()).unwrap_or_default();
+        self
+    }
+}
+
+/// Represents all listening sessions, ordered by their start.
+#[repr(transparent)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionContainer(pub Vec<Session>);
+
+impl SessionContainer {
+    /// Groups the streams of every user into sessions, a session ends when nothing is played for longer than `idle_gap`.
+    pub fn new(value: CompressedEndStreamWithKindContainer, idle_gap: Duration) -> Self {
+        let mut per_user: BTreeMap<String, Vec<Play>> = BTreeMap::new();
+        for (username, _conn_country, platform, kind, artist, _album, track, info) in value {
+            let plays = per_user.entry(username.clone()).or_default();
+            for (ts, entry) in info.end_stream_log.iter() {
+                plays.push(Play {
+                    start: *ts - entry.ms_played,
+                    end: *ts,
+                    username: username.clone(),
+                    platform: platform.clone(),
+                    kind: kind.clone(),
+                    artist_or_podcast: artist.clone(),
+                    track_or_episode: track.clone(),
+                });
+            }
+        }
+        let mut out = Vec::new();
+        for (_, mut plays) in per_user {
+            plays.sort();
+            let mut current: Option<Session> = None;
+            for play in plays {
+                match current.as_mut() {
+                    Some(session) if play.start - session.end <= idle_gap => {
+                        session.end = session.end.max(play.end);
+                        session.plays.push(play);
+                    }
+                    _ => {
+                        if let Some(session) = current.replace(Session::new(play)) {
+                            out.push(session.finish());
+                        }
+                    }
+                }
+            }
+            if let Some(session) = current {
+                out.push(session.finish());
+            }
+        }
+        out.sort_by_key(|x| x.start);
+        Self(out)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Total time of all sessions.
+    pub fn total_length(&self) -> Duration {
+        self.0
+            .iter()
+            .fold(Duration::zero(), |acc, x| acc + x.length())
+    }
+
+    /// The `count` longest sessions, longest first.
+    pub fn longest(&self, count: usize) -> Vec<&Session> {
+        let mut sorted: Vec<&Session> = self.0.iter().collect();
+        sorted.sort_by_key(|x| std::cmp::Reverse(x.length()));
+        sorted.truncate(count);
+        sorted
+    }
+
+    /// The amount of sessions shorter than each bound, and the amount of sessions at least as long as the last bound.
+    pub fn length_distribution(&self, bounds: &[Duration]) -> Vec<usize> {
+        let mut out = vec![0; bounds.len() + 1];
+        for session in &self.0 {
+            let bucket = bounds
+                .iter()
+                .position(|x| session.length() < *x)
+                .unwrap_or(bounds.len());
+            out[bucket] += 1;
+        }
+        out
+    }
+
+    /// The amount of streams per session, sorted ascending.
+    pub fn plays_per_session(&self) -> Vec<usize> {
+        let mut out: Vec<usize> = self.0.iter().map(|x| x.plays.len()).collect();
+        out.sort_unstable();
+        out
+    }
+
+    /// The amount of sessions and their total time per platform.
+    pub fn by_platform(&self) -> BTreeMap<&str, (usize, Duration)> {
+        let mut out: BTreeMap<&str, (usize, Duration)> = BTreeMap::new();
+        for session in &self.0 {
+            let entry = out
+                .entry(&session.platform)
+                .or_insert((0, Duration::zero()));
+            entry.0 += 1;
+            entry.1 += session.length();
+        }
+        out
+    }
+}
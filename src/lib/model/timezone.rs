@@ -0,0 +1,239 @@
+//! This module describes how the timestamps, which Spotify records in UTC, are converted into local time.
+//!
+//! The persistent binary file always stays in UTC, the conversion happens after loading it.
+
+use std::{fmt::Display, str::FromStr};
+
+use chrono::{Local, NaiveDateTime, TimeZone as _, Utc};
+use chrono_tz::Tz;
+
+use crate::{Error, Result};
+
+use super::compression::{CompressedEndStreamWithKindContainer, EndStreamLog};
+
+/// The value of `--tz` that infers the time zone from the country the stream was played in.
+pub const FROM_COUNTRY: &str = "country";
+
+/// The time zone in which the timestamps are bucketed and displayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeZone {
+    /// Keep the timestamps as Spotify recorded them.
+    #[default]
+    Utc,
+    /// A single IANA time zone, e.g. `Europe/Amsterdam`.
+    Fixed(Tz),
+    /// The time zone of `conn_country`, so travelling is taken into account.
+    ///
+    /// Countries that span several time zones use the zone of their capital or largest population, unknown countries stay in UTC.
+    FromCountry,
+}
+
+impl TimeZone {
+    /// The time zone to use for a stream played in the given country, `None` when it stays in UTC.
+    pub fn zone(&self, conn_country: &str) -> Option<Tz> {
+        match self {
+            TimeZone::Utc => None,
+            TimeZone::Fixed(tz) => Some(*tz),
+            TimeZone::FromCountry => country_time_zone(conn_country),
+        }
+    }
+
+    /// Converts a UTC timestamp into local time.
+    pub fn to_local(&self, utc: NaiveDateTime, conn_country: &str) -> NaiveDateTime {
+        match self.zone(conn_country) {
+            Some(tz) => tz.from_utc_datetime(&utc).naive_local(),
+            None => utc,
+        }
+    }
+
+    /// The current local time, `FromCountry` uses the time zone of this machine.
+    pub fn now(&self) -> NaiveDateTime {
+        let utc = Utc::now();
+        match self {
+            TimeZone::Utc => utc.naive_utc(),
+            TimeZone::Fixed(tz) => utc.with_timezone(tz).naive_local(),
+            TimeZone::FromCountry => utc.with_timezone(&Local).naive_local(),
+        }
+    }
+}
+
+impl Display for TimeZone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimeZone::Utc => write!(f, "UTC"),
+            TimeZone::Fixed(tz) => write!(f, "{}", tz.name()),
+            TimeZone::FromCountry => write!(f, "{FROM_COUNTRY}"),
+        }
+    }
+}
+
+/// Parses `UTC`, an IANA time zone such as `Europe/Amsterdam`, or `country`.
+impl FromStr for TimeZone {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("utc") {
+            return Ok(TimeZone::Utc);
+        }
+        if s.eq_ignore_ascii_case(FROM_COUNTRY) {
+            return Ok(TimeZone::FromCountry);
+        }
+        s.parse::<Tz>().map(TimeZone::Fixed).map_err(|_| {
+            Error::InvalidInput(format!(
+                "Unknown time zone `{s}`, expected e.g. `Europe/Amsterdam`, `UTC` or `{FROM_COUNTRY}`"
+            ))
+        })
+    }
+}
+
+impl CompressedEndStreamWithKindContainer {
+    /// Converts every timestamp of the log from UTC into local time.
+    pub fn to_local_time(mut self, time_zone: &TimeZone) -> Self {
+        if *time_zone == TimeZone::Utc {
+            return self;
+        }
+        for countries in self.0.values_mut() {
+            for (conn_country, platforms) in countries.iter_mut() {
+                for kinds in platforms.values_mut() {
+                    for artists in kinds.values_mut() {
+                        for albums in artists.values_mut() {
+                            for tracks in albums.values_mut() {
+                                for info in tracks.values_mut() {
+                                    info.end_stream_log = std::mem::take(&mut info.end_stream_log)
+                                        .into_iter()
+                                        .map(|(ts, entry)| {
+                                            (time_zone.to_local(ts, conn_country), entry)
+                                        })
+                                        .collect::<EndStreamLog>();
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        self
+    }
+}
+
+/// The time zone of a country, by its ISO 3166-1 alpha-2 code as used in `conn_country`.
+pub fn country_time_zone(conn_country: &str) -> Option<Tz> {
+    let tz = match conn_country.to_ascii_uppercase().as_str() {
+        "AD" => Tz::Europe__Andorra,
+        "AE" => Tz::Asia__Dubai,
+        "AG" => Tz::America__Antigua,
+        "AL" => Tz::Europe__Tirane,
+        "AM" => Tz::Asia__Yerevan,
+        "AO" => Tz::Africa__Luanda,
+        "AR" => Tz::America__Argentina__Buenos_Aires,
+        "AT" => Tz::Europe__Vienna,
+        "AU" => Tz::Australia__Sydney,
+        "AZ" => Tz::Asia__Baku,
+        "BA" => Tz::Europe__Sarajevo,
+        "BB" => Tz::America__Barbados,
+        "BD" => Tz::Asia__Dhaka,
+        "BE" => Tz::Europe__Brussels,
+        "BG" => Tz::Europe__Sofia,
+        "BH" => Tz::Asia__Bahrain,
+        "BO" => Tz::America__La_Paz,
+        "BR" => Tz::America__Sao_Paulo,
+        "BS" => Tz::America__Nassau,
+        "BY" => Tz::Europe__Minsk,
+        "BZ" => Tz::America__Belize,
+        "CA" => Tz::America__Toronto,
+        "CH" => Tz::Europe__Zurich,
+        "CL" => Tz::America__Santiago,
+        "CM" => Tz::Africa__Douala,
+        "CN" => Tz::Asia__Shanghai,
+        "CO" => Tz::America__Bogota,
+        "CR" => Tz::America__Costa_Rica,
+        "CY" => Tz::Asia__Nicosia,
+        "CZ" => Tz::Europe__Prague,
+        "DE" => Tz::Europe__Berlin,
+        "DK" => Tz::Europe__Copenhagen,
+        "DO" => Tz::America__Santo_Domingo,
+        "DZ" => Tz::Africa__Algiers,
+        "EC" => Tz::America__Guayaquil,
+        "EE" => Tz::Europe__Tallinn,
+        "EG" => Tz::Africa__Cairo,
+        "ES" => Tz::Europe__Madrid,
+        "FI" => Tz::Europe__Helsinki,
+        "FR" => Tz::Europe__Paris,
+        "GB" => Tz::Europe__London,
+        "GE" => Tz::Asia__Tbilisi,
+        "GH" => Tz::Africa__Accra,
+        "GR" => Tz::Europe__Athens,
+        "GT" => Tz::America__Guatemala,
+        "HK" => Tz::Asia__Hong_Kong,
+        "HN" => Tz::America__Tegucigalpa,
+        "HR" => Tz::Europe__Zagreb,
+        "HU" => Tz::Europe__Budapest,
+        "ID" => Tz::Asia__Jakarta,
+        "IE" => Tz::Europe__Dublin,
+        "IL" => Tz::Asia__Jerusalem,
+        "IN" => Tz::Asia__Kolkata,
+        "IQ" => Tz::Asia__Baghdad,
+        "IS" => Tz::Atlantic__Reykjavik,
+        "IT" => Tz::Europe__Rome,
+        "JM" => Tz::America__Jamaica,
+        "JO" => Tz::Asia__Amman,
+        "JP" => Tz::Asia__Tokyo,
+        "KE" => Tz::Africa__Nairobi,
+        "KR" => Tz::Asia__Seoul,
+        "KW" => Tz::Asia__Kuwait,
+        "KZ" => Tz::Asia__Almaty,
+        "LB" => Tz::Asia__Beirut,
+        "LI" => Tz::Europe__Vaduz,
+        "LK" => Tz::Asia__Colombo,
+        "LT" => Tz::Europe__Vilnius,
+        "LU" => Tz::Europe__Luxembourg,
+        "LV" => Tz::Europe__Riga,
+        "MA" => Tz::Africa__Casablanca,
+        "MC" => Tz::Europe__Monaco,
+        "MD" => Tz::Europe__Chisinau,
+        "ME" => Tz::Europe__Podgorica,
+        "MK" => Tz::Europe__Skopje,
+        "MT" => Tz::Europe__Malta,
+        "MX" => Tz::America__Mexico_City,
+        "MY" => Tz::Asia__Kuala_Lumpur,
+        "NG" => Tz::Africa__Lagos,
+        "NI" => Tz::America__Managua,
+        "NL" => Tz::Europe__Amsterdam,
+        "NO" => Tz::Europe__Oslo,
+        "NZ" => Tz::Pacific__Auckland,
+        "OM" => Tz::Asia__Muscat,
+        "PA" => Tz::America__Panama,
+        "PE" => Tz::America__Lima,
+        "PH" => Tz::Asia__Manila,
+        "PK" => Tz::Asia__Karachi,
+        "PL" => Tz::Europe__Warsaw,
+        "PR" => Tz::America__Puerto_Rico,
+        "PT" => Tz::Europe__Lisbon,
+        "PY" => Tz::America__Asuncion,
+        "QA" => Tz::Asia__Qatar,
+        "RO" => Tz::Europe__Bucharest,
+        "RS" => Tz::Europe__Belgrade,
+        "RU" => Tz::Europe__Moscow,
+        "SA" => Tz::Asia__Riyadh,
+        "SE" => Tz::Europe__Stockholm,
+        "SG" => Tz::Asia__Singapore,
+        "SI" => Tz::Europe__Ljubljana,
+        "SK" => Tz::Europe__Bratislava,
+        "SV" => Tz::America__El_Salvador,
+        "TH" => Tz::Asia__Bangkok,
+        "TN" => Tz::Africa__Tunis,
+        "TR" => Tz::Europe__Istanbul,
+        "TT" => Tz::America__Port_of_Spain,
+        "TW" => Tz::Asia__Taipei,
+        "TZ" => Tz::Africa__Dar_es_Salaam,
+        "UA" => Tz::Europe__Kiev,
+        "UG" => Tz::Africa__Kampala,
+        "US" => Tz::America__New_York,
+        "UY" => Tz::America__Montevideo,
+        "UZ" => Tz::Asia__Tashkent,
+        "VN" => Tz::Asia__Ho_Chi_Minh,
+        "ZA" => Tz::Africa__Johannesburg,
+        _ => return None,
+    };
+    Some(tz)
+}
@@ -0,0 +1,267 @@
+//! This module describes a year in review, in the style of Spotify Wrapped, computed from the complete streaming history.
+
+use std::collections::BTreeMap;
+
+use chrono::{Duration, DurationRound, NaiveDate, NaiveDateTime};
+
+use super::{
+    compression::{CompressedEndStreamWithKindContainer, EndStreamLogEntry, STREAM_THRESHOLD_MS},
+    end_stream::EndStreamKind,
+    filter::DateRange,
+};
+
+/// An artist, track, album or podcast, and how much it was listened to during the period.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ranked {
+    /// The name of the artist, track, album or podcast.
+    pub name: String,
+    /// The artist of a track or album, `None` for artists and podcasts.
+    pub artist: Option<String>,
+    pub total_ms_played: Duration,
+    /// The amount of streams that were played for at least `STREAM_THRESHOLD_MS`.
+    pub stream_count: usize,
+}
+
+impl Ranked {
+    fn new(name: String, artist: Option<String>) -> Self {
+        Self {
+            name,
+            artist,
+            total_ms_played: Duration::zero(),
+            stream_count: 0,
+        }
+    }
+
+    fn add(&mut self, entry: &EndStreamLogEntry) {
+        self.total_ms_played += entry.ms_played;
+        if entry.counts_as_stream(Duration::milliseconds(STREAM_THRESHOLD_MS)) {
+            self.stream_count += 1;
+        }
+    }
+}
+
+/// Consecutive days on which something was played, both ends inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Streak {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+impl Streak {
+    /// The amount of days in the streak.
+    pub fn days(&self) -> i64 {
+        (self.end - self.start).num_days() + 1
+    }
+}
+
+/// The year in review, or the review of any other period.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wrapped {
+    pub range: DateRange,
+    /// Total listening time, of music, podcasts and everything else.
+    pub total_ms_played: Duration,
+    /// The amount of streams that were played for at least `STREAM_THRESHOLD_MS`.
+    pub stream_count: usize,
+    pub distinct_artists: usize,
+    pub distinct_tracks: usize,
+    pub top_artists: Vec<Ranked>,
+    pub top_tracks: Vec<Ranked>,
+    pub top_albums: Vec<Ranked>,
+    pub top_podcasts: Vec<Ranked>,
+    /// Artists that were streamed for the first time during the period, most listened first.
+    pub discoveries: Vec<Ranked>,
+    /// The day with the most listening time.
+    pub busiest_day: Option<(NaiveDate, Duration)>,
+    /// The longest run of days with any listening, the earliest one on ties.
+    pub longest_streak: Option<Streak>,
+    /// The track that was skipped most often, and the amount of times it was skipped.
+    pub most_skipped: Option<(Ranked, usize)>,
+    /// The listening time per platform, most used first, see `platform_name`.
+    pub platforms: Vec<(String, Duration)>,
+}
+
+impl Wrapped {
+    /// Reviews the streams within `range`, showing the `top` entries of every ranking.
+    ///
+    /// The streams before the period are only used to tell which artists are new, so pass the unfiltered data.
+    pub fn new(value: CompressedEndStreamWithKindContainer, range: &DateRange, top: usize) -> Self {
+        let mut total_ms_played = Duration::zero();
+        let mut stream_count = 0;
+        let mut artists: BTreeMap<String, Ranked> = BTreeMap::new();
+        let mut tracks: BTreeMap<(String, String), Ranked> = BTreeMap::new();
+        let mut albums: BTreeMap<(String, String), Ranked> = BTreeMap::new();
+        let mut podcasts: BTreeMap<String, Ranked> = BTreeMap::new();
+        let mut first_played: BTreeMap<String, NaiveDateTime> = BTreeMap::new();
+        let mut skips: BTreeMap<(String, String), usize> = BTreeMap::new();
+        let mut days: BTreeMap<NaiveDate, Duration> = BTreeMap::new();
+        let mut platforms: BTreeMap<String, Duration> = BTreeMap::new();
+        for (_, _, platform, kind, artist, album, track, info) in value {
+            if kind == EndStreamKind::EndSong {
+                if let Some(first) = info.first_play() {
+                    first_played
+                        .entry(artist.clone())
+                        .and_modify(|x| *x = (*x).min(first))
+                        .or_insert(first);
+                }
+            }
+            for (ts, entry) in info.end_stream_log.iter() {
+                if !range.contains(ts) {
+                    continue;
+                }
+                total_ms_played += entry.ms_played;
+                if entry.counts_as_stream(Duration::milliseconds(STREAM_THRESHOLD_MS)) {
+                    stream_count += 1;
+                }
+                // Only the part of the stream within the period counts towards its days.
+                let start = *ts - entry.ms_played;
+                add_per_day(
+                    &mut days,
+                    range.start.map_or(start, |x| x.max(start)),
+                    range.end.map_or(*ts, |x| x.min(*ts)),
+                );
+                *platforms
+                    .entry(platform_name(&platform).to_string())
+                    .or_insert_with(Duration::zero) += entry.ms_played;
+                match kind {
+                    EndStreamKind::EndSong => {
+                        artists
+                            .entry(artist.clone())
+                            .or_insert_with(|| Ranked::new(artist.clone(), None))
+                            .add(entry);
+                        tracks
+                            .entry((artist.clone(), track.clone()))
+                            .or_insert_with(|| Ranked::new(track.clone(), Some(artist.clone())))
+                            .add(entry);
+                        albums
+                            .entry((artist.clone(), album.clone()))
+                            .or_insert_with(|| Ranked::new(album.clone(), Some(artist.clone())))
+                            .add(entry);
+                        if entry.skipped == Some(true) {
+                            *skips.entry((artist.clone(), track.clone())).or_default() += 1;
+                        }
+                    }
+                    EndStreamKind::EndEpisode => podcasts
+                        .entry(album.clone())
+                        .or_insert_with(|| Ranked::new(album.clone(), None))
+                        .add(entry),
+                    EndStreamKind::EndVideoOrElse => {}
+                }
+            }
+        }
+
+        let mut most_skipped: Option<(&(String, String), usize)> = None;
+        for (key, amount) in &skips {
+            if most_skipped.is_none_or(|(_, x)| *amount > x) {
+                most_skipped = Some((key, *amount));
+            }
+        }
+        let most_skipped =
+            most_skipped.and_then(|(key, amount)| Some((tracks.get(key)?.clone(), amount)));
+        let discoveries = artists
+            .values()
+            .filter(|x| {
+                first_played
+                    .get(&x.name)
+                    .is_some_and(|first| range.start.is_none_or(|start| start <= *first))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut busiest_day: Option<(NaiveDate, Duration)> = None;
+        for (day, time) in &days {
+            if busiest_day.is_none_or(|(_, x)| *time > x) {
+                busiest_day = Some((*day, *time));
+            }
+        }
+        let mut platforms: Vec<(String, Duration)> = platforms.into_iter().collect();
+        platforms.sort_by_key(|(_, x)| std::cmp::Reverse(*x));
+
+        Self {
+            range: *range,
+            total_ms_played,
+            stream_count,
+            distinct_artists: artists.len(),
+            distinct_tracks: tracks.len(),
+            top_artists: most_played(artists.into_values(), top),
+            top_tracks: most_played(tracks.into_values(), top),
+            top_albums: most_played(albums.into_values(), top),
+            top_podcasts: most_played(podcasts.into_values(), top),
+            discoveries: most_played(discoveries, top),
+            busiest_day,
+            longest_streak: longest_streak(days.keys().copied()),
+            most_skipped,
+            platforms,
+        }
+    }
+}
+
+/// Groups the raw platform, e.g. `Android OS 9 API 28 (samsung, SM-G950F)` or `Windows 10 (10.0.19044; x64)`, by its operating system or client.
+///
+/// Unknown platforms are returned as they are.
+pub fn platform_name(platform: &str) -> &str {
+    let lowercase = platform.to_ascii_lowercase();
+    if lowercase.starts_with("android") {
+        "Android"
+    } else if lowercase.starts_with("ios") {
+        "iOS"
+    } else if lowercase.starts_with("windows") {
+        "Windows"
+    } else if lowercase.starts_with("os x") || lowercase.starts_with("macos") {
+        "macOS"
+    } else if lowercase.starts_with("linux") {
+        "Linux"
+    } else if lowercase.starts_with("web_player") || lowercase.starts_with("web player") {
+        "Web Player"
+    } else {
+        platform
+    }
+}
+
+/// The `count` entries with the most listening time, ties keep their lexicographical ordering.
+fn most_played<I>(entries: I, count: usize) -> Vec<Ranked>
+where
+    I: IntoIterator<Item = Ranked>,
+{
+    let mut out: Vec<Ranked> = entries.into_iter().collect();
+    out.sort_by_key(|x| std::cmp::Reverse(x.total_ms_played));
+    out.truncate(count);
+    out
+}
+
+/// Adds the time between the start and end of a stream, split over the days it spans.
+fn add_per_day(days: &mut BTreeMap<NaiveDate, Duration>, start: NaiveDateTime, end: NaiveDateTime) {
+    let mut current = start;
+    while current < end {
+        let next_day = current
+            .duration_trunc(Duration::days(1))
+            .map_or(end, |x| x + Duration::days(1));
+        let until = next_day.min(end);
+        *days.entry(current.date()).or_insert_with(Duration::zero) += until - current;
+        current = until;
+    }
+}
+
+/// The longest run of consecutive days, the earliest one on ties.
+fn longest_streak<I>(sorted_days: I) -> Option<Streak>
+where
+    I: IntoIterator<Item = NaiveDate>,
+{
+    let mut longest: Option<Streak> = None;
+    let mut current: Option<Streak> = None;
+    for day in sorted_days {
+        current = match current {
+            Some(streak) if streak.end.succ_opt() == Some(day) => {
+                Some(Streak { end: day, ..streak })
+            }
+            _ => Some(Streak {
+                start: day,
+                end: day,
+            }),
+        };
+        if let Some(streak) = current {
+            if longest.is_none_or(|x| streak.days() > x.days()) {
+                longest = Some(streak);
+            }
+        }
+    }
+    longest
+}
@@ -1,7 +1,6 @@
 //! This module describes the deserialization process, i.e. loading from persistent files.
 
 use chrono::{Duration, NaiveDateTime};
-use eyre::Result;
 use serde::{de, Deserialize};
 
 pub fn naive_date_time_deserialization<'de, D>(deserializer: D) -> Result<NaiveDateTime, D::Error>
@@ -23,3 +22,15 @@ where
         deserializer,
     )?))
 }
+
+/// The account data export uses a different, less precise, timestamp format: "2023-02-22 07:01".
+pub fn legacy_date_time_deserialization<'de, D>(deserializer: D) -> Result<NaiveDateTime, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let s: String = Deserialize::deserialize(deserializer)?;
+    NaiveDateTime::parse_from_str(&s, "%Y-%m-%d %H:%M").map_err(|parse_error| {
+        let custom_error_msg = format!("Failed to parse NaiveDateTime: {}", parse_error);
+        de::Error::custom(custom_error_msg)
+    })
+}
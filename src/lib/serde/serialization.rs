@@ -1,7 +1,6 @@
 //! This module describes the serialization process, i.e. saving to persistent files.
 
 use chrono::{Duration, NaiveDateTime};
-use eyre::Result;
 use serde::{Serialize, Serializer};
 
 pub fn duration_serialization<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
@@ -24,3 +23,17 @@ where
         serializer,
     )
 }
+
+// "2023-02-22 07:01"
+pub fn legacy_date_time_serialization<S>(
+    naive_date_time: &NaiveDateTime,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    Serialize::serialize(
+        &naive_date_time.format("%Y-%m-%d %H:%M").to_string(),
+        serializer,
+    )
+}
@@ -4,20 +4,34 @@
 pub mod tests;
 
 use std::{
-    fmt::{Debug, Display},
-    fs::File,
-    io::Write,
+    fmt::{Debug, Display, Write as _},
+    fs::{create_dir_all, File},
+    io::{Read, Write},
     path::{Path, PathBuf},
+    time::Instant,
 };
 
-use clap::{command, Parser, Subcommand};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
 use comfy_table::{presets::ASCII_MARKDOWN, Table};
-use eyre::{Ok, Result};
+use eyre::{eyre, Ok, Result, WrapErr};
 use spotify_stats::model::{
-    compression::{CompressedEndStreamWithKindContainer, EndStreamKindCompressedLogContainer},
-    end_stream::FromFolderJson,
-    Persist,
+    aggregate::{AggregateContainer, Level},
+    cache::{Cache, SourceFingerprint},
+    codec::Codec,
+    compression::{
+        CompressedEndStreamWithKindContainer, Dimension, EndStreamKindCompressedLog,
+        EndStreamKindCompressedLogContainer, STREAM_THRESHOLD_MS,
+    },
+    end_stream::{stream_folder, stream_zip},
+    filter::{DateRange, Filter, MatchMode},
+    heatmap::{Heatmap, HeatmapContainer, HeatmapSplit, HOURS, WEEKDAYS},
+    import::ImportOptions,
+    session::SessionContainer,
+    timezone::TimeZone,
+    wrapped::{Ranked, Wrapped},
+    FormatError, Header, Persist,
 };
 
 #[derive(Debug, Clone, Subcommand)]
@@ -36,12 +50,89 @@ enum RawFormat {
     },
     /// Displays the internal data used in raw binary format.
     Bin {
-        /// Apply compression
+        /// Apply compression, using the codec given by `--codec`.
         #[arg(short, long)]
         compression: bool,
     },
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum HeatmapBy {
+    Artist,
+    Kind,
+}
+
+impl From<HeatmapBy> for HeatmapSplit {
+    fn from(value: HeatmapBy) -> Self {
+        match value {
+            HeatmapBy::Artist => HeatmapSplit::Artist,
+            HeatmapBy::Kind => HeatmapSplit::Kind,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum HeatmapFormat {
+    /// A grid shaded by listening time, for the terminal.
+    Shaded,
+    /// One row per heatmap, weekday and hour.
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum WrappedFormat {
+    /// A report for the terminal.
+    Text,
+    /// A standalone web page, e.g. `--format html --file wrapped.html`.
+    Html,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SplitBy {
+    Username,
+    Country,
+    Platform,
+}
+
+impl From<SplitBy> for Dimension {
+    fn from(value: SplitBy) -> Self {
+        match value {
+            SplitBy::Username => Dimension::Username,
+            SplitBy::Country => Dimension::Country,
+            SplitBy::Platform => Dimension::Platform,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SearchMode {
+    /// Case-insensitive substring match.
+    Contains,
+    /// Exact match.
+    Exact,
+    /// Regular expression match.
+    Regex,
+}
+
+impl From<SearchMode> for MatchMode {
+    fn from(value: SearchMode) -> Self {
+        match value {
+            SearchMode::Contains => MatchMode::Contains,
+            SearchMode::Exact => MatchMode::Exact,
+            SearchMode::Regex => MatchMode::Regex,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SortBy {
+    /// Total play time.
+    Duration,
+    /// Amount of plays that pass the threshold.
+    Plays,
+}
+
 #[derive(Debug, Clone, Subcommand)]
 enum Format {
     /// Only the top most played, or when passing the `reversed` flag the top least played.
@@ -52,9 +143,42 @@ enum Format {
         /// Reverse the sorting, i.e. displaying the `top least` played songs.
         #[arg(short, long)]
         reversed: bool,
+        /// Rank separately per username, country and/or platform, instead of across all of them.
+        #[arg(long, value_enum, value_delimiter = ',')]
+        split_by: Vec<SplitBy>,
+        /// Rank by total play time or by the amount of plays.
+        #[arg(short, long, value_enum, default_value_t = SortBy::Duration)]
+        by: SortBy,
+        /// The minimal play time in milliseconds for a play to count, when ranking by plays.
+        #[arg(short, long, default_value_t = STREAM_THRESHOLD_MS)]
+        threshold: i64,
     },
     /// Don't sort use default lexicographical ordering.
     Lex,
+    /// Rank the artists and podcasts by their total play time.
+    Artists {
+        /// Display the `top <COUNT>` entries.
+        #[arg(short, long)]
+        count: Option<usize>,
+        /// Reverse the sorting, i.e. displaying the `top least` played artists.
+        #[arg(short, long)]
+        reversed: bool,
+        /// Rank music and podcasts together, instead of separately.
+        #[arg(long)]
+        combined: bool,
+    },
+    /// Rank the albums and shows by their total play time.
+    Albums {
+        /// Display the `top <COUNT>` entries.
+        #[arg(short, long)]
+        count: Option<usize>,
+        /// Reverse the sorting, i.e. displaying the `top least` played albums.
+        #[arg(short, long)]
+        reversed: bool,
+        /// Rank music and podcasts together, instead of separately.
+        #[arg(long)]
+        combined: bool,
+    },
 }
 
 #[derive(Debug, Clone, Subcommand)]
@@ -76,10 +200,78 @@ enum SpotifyStatsCommand {
         /// Only show entries that match this track name, and/or matching other search queries.
         #[arg(long)]
         track: Option<String>,
+        /// How the search queries are matched against the names.
+        #[arg(short, long, value_enum, default_value_t = SearchMode::Contains)]
+        mode: SearchMode,
         /// Specify a specify format to use.
         #[command(subcommand)]
         format: Format,
     },
+    /// Merge a new export into the persistent binary file, skipping the streams that were already imported.
+    ///
+    /// Exports overlap, so streams are identified by their username, timestamp and artist, album and track names.
+    /// The export is read one record at a time, only the new streams are held in memory until they are merged.
+    Import {
+        /// The folder, or downloaded `.zip` archive, of the new export.
+        path: PathBuf,
+    },
+    /// Group the streams into listening sessions, separated by a pause of more than `--gap`, and report on them.
+    ///
+    /// The start of a stream is reconstructed from the moment it ended and how long it was played.
+    Sessions {
+        /// Redirect output to a file, with the given path.
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+        /// The pause after which a new session starts, e.g. `30m` or `2h`.
+        #[arg(short, long, value_parser = parse_duration, default_value = "30m")]
+        gap: Duration,
+        /// Display the `top <COUNT>` longest sessions.
+        #[arg(short, long, default_value_t = 10)]
+        count: usize,
+    },
+    /// Show when you listen: the listening time per weekday and hour of the day.
+    ///
+    /// The time of every stream is spread over the hours it was played in.
+    Heatmap {
+        /// Redirect output to a file, with the given path.
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+        /// Draw a separate heatmap per artist or podcast, or per kind.
+        #[arg(short, long, value_enum)]
+        by: Option<HeatmapBy>,
+        /// Only show the `top <COUNT>` most listened heatmaps.
+        #[arg(short, long)]
+        count: Option<usize>,
+        /// How to present the heatmaps.
+        #[arg(long, value_enum, default_value_t = HeatmapFormat::Shaded)]
+        format: HeatmapFormat,
+    },
+    /// Your own Wrapped: the total listening time, top artists, tracks, albums and podcasts, new discoveries, the busiest day, the longest streak, the most skipped track and the platforms used.
+    ///
+    /// Reviews the period given by `--year`, or any other period, and the current year when none is given.
+    /// The streams before the period are only used to tell which artists are new.
+    Wrapped {
+        /// Redirect output to a file, with the given path.
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+        /// Display the `top <COUNT>` entries of every ranking.
+        #[arg(short, long, default_value_t = 5)]
+        count: usize,
+        /// How to present the review.
+        #[arg(long, value_enum, default_value_t = WrappedFormat::Text)]
+        format: WrappedFormat,
+    },
+    /// Compare the compression codecs on your own streaming data: the size of the persistent binary file, and how long it takes to save and load it in memory.
+    Benchmark {
+        /// Redirect output to a file, with the given path.
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+    },
+    /// Load the persistent binary file and check its header, checksum and content.
+    Verify {
+        /// The file to check, instead of the one of the profile, e.g. a backup.
+        path: Option<PathBuf>,
+    },
     /// Display the streaming data using the raw internal data format.
     ///
     /// Either using the internal Rust representation or formatting as JSON data.
@@ -96,18 +288,159 @@ enum SpotifyStatsCommand {
     },
 }
 
+#[derive(Args, Debug)]
+struct PeriodArgs {
+    /// Only use streams from this moment onward, e.g. `2022-01-01` or `2022-01-01T12:00:00`.
+    #[arg(long, global = true, value_parser = parse_since)]
+    since: Option<NaiveDateTime>,
+    /// Only use streams up to and including this moment, e.g. `2022-12-31` or `2022-12-31T12:00:00`.
+    #[arg(long, global = true, value_parser = parse_until)]
+    until: Option<NaiveDateTime>,
+    /// Only use streams from this year, e.g. `2022`.
+    #[arg(long, global = true, value_parser = parse_year, conflicts_with_all = ["month", "quarter", "last"])]
+    year: Option<DateRange>,
+    /// Only use streams from this quarter, e.g. `2022-Q3`.
+    #[arg(long, global = true, value_parser = parse_quarter, conflicts_with_all = ["month", "last"])]
+    quarter: Option<DateRange>,
+    /// Only use streams from this month, e.g. `2023-05`.
+    #[arg(long, global = true, value_parser = parse_month, conflicts_with_all = ["last"])]
+    month: Option<DateRange>,
+    /// Only use streams from the last period, e.g. `90d`, `12w` or `48h`.
+    #[arg(long, global = true, value_parser = parse_duration)]
+    last: Option<Duration>,
+}
+
+impl PeriodArgs {
+    /// The period to use, `--last` is measured back from `now`.
+    fn range(&self, now: NaiveDateTime) -> Result<DateRange> {
+        let last = self.last.map(|x| DateRange::last(x, now)).transpose()?;
+        let range = [self.year, self.quarter, self.month, last]
+            .into_iter()
+            .flatten()
+            .fold(
+                DateRange::between(self.since, self.until)?,
+                DateRange::intersect,
+            );
+        if range.is_empty() {
+            return Err(eyre!(
+                "The period is empty, the given period options don't overlap"
+            ));
+        }
+        Ok(range)
+    }
+}
+
+fn parse_timestamp(s: &str) -> Result<(NaiveDateTime, bool)> {
+    if let Result::Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok((date.and_hms_opt(0, 0, 0).unwrap(), true));
+    }
+    ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+        .map(|x| (x, false))
+        .ok_or_else(|| eyre!("expected `YYYY-MM-DD` or `YYYY-MM-DDTHH:MM:SS`"))
+}
+
+fn parse_profile(s: &str) -> Result<String> {
+    if s.is_empty() || s.starts_with('.') || s.contains(['/', '\\']) {
+        return Err(eyre!("A profile is a plain name, e.g. `alice`"));
+    }
+    Ok(s.to_string())
+}
+
+fn parse_since(s: &str) -> Result<NaiveDateTime> {
+    Ok(parse_timestamp(s)?.0)
+}
+
+fn parse_until(s: &str) -> Result<NaiveDateTime> {
+    // The range end is exclusive, so make sure the given day or second itself is included.
+    let (timestamp, is_date) = parse_timestamp(s)?;
+    if is_date {
+        Ok(timestamp + Duration::days(1))
+    } else {
+        Ok(timestamp + Duration::seconds(1))
+    }
+}
+
+fn parse_year(s: &str) -> Result<DateRange> {
+    Ok(DateRange::year(s.parse()?)?)
+}
+
+fn parse_quarter(s: &str) -> Result<DateRange> {
+    let (year, quarter) = s
+        .split_once("-Q")
+        .or_else(|| s.split_once("-q"))
+        .ok_or_else(|| eyre!("expected `YYYY-Qn`"))?;
+    Ok(DateRange::quarter(year.parse()?, quarter.parse()?)?)
+}
+
+fn parse_month(s: &str) -> Result<DateRange> {
+    let (year, month) = s
+        .split_once('-')
+        .ok_or_else(|| eyre!("expected `YYYY-MM`"))?;
+    Ok(DateRange::month(year.parse()?, month.parse()?)?)
+}
+
+fn parse_duration(s: &str) -> Result<Duration> {
+    let split = s.len() - s.chars().last().map_or(0, char::len_utf8);
+    let amount: i64 = s[..split].parse()?;
+    if amount < 0 {
+        return Err(eyre!("expected a duration that is not negative"));
+    }
+    match &s[split..] {
+        "m" => Duration::try_minutes(amount),
+        "h" => Duration::try_hours(amount),
+        "d" => Duration::try_days(amount),
+        "w" => Duration::try_weeks(amount),
+        _ => return Err(eyre!("expected a unit of `m`, `h`, `d` or `w`")),
+    }
+    .ok_or_else(|| eyre!("the duration `{s}` is too long"))
+}
+
 /// Command Line Interface that can process your Spotify Streaming Data.
 ///
 /// In the commands section you'll find the different formatting options.
 #[derive(Parser, Debug)]
 #[clap(version, author)]
 struct SpotifyStats {
-    /// FIRST RUN: The folder to extract the streaming data from.
+    /// FIRST RUN: The folder, or downloaded `.zip` archive, to extract the streaming data from.
     ///
-    /// After first run: a persistent binary file `.\spotify_stats.bin` is created, relative to this executable, that contains all the relevant data compressed.
+    /// After first run: a persistent binary file is created in the data directory of the user, e.g. `~/.local/share/spotify_stats/default.bin`, that contains all the relevant data compressed, see `--cache` and `--profile`.
     /// This executable first tries to find this file, and if it is not present only then will an error be displayed, asking you to provide this folder.
+    /// The file is rebuilt automatically whenever the export it was built from changes, unless other exports were merged into it with `import`.
     #[arg(short, long)]
     data: Option<PathBuf>,
+    /// Skip malformed records when extracting the streaming data, and report them afterwards, instead of failing.
+    #[arg(long, global = true)]
+    lenient: bool,
+    /// Parse the files of the export one after another, instead of in parallel.
+    #[arg(long, global = true)]
+    serial: bool,
+    /// Store the persistent binary file at this path, instead of in the data directory of the user.
+    #[arg(long, global = true, conflicts_with = "profile")]
+    cache: Option<PathBuf>,
+    /// The named dataset to use, so the exports of several people can live side by side.
+    #[arg(long, global = true, default_value = DEFAULT_PROFILE, value_parser = parse_profile)]
+    profile: String,
+    /// Rebuild the persistent binary file from the export, even when it is up to date.
+    #[arg(long, global = true)]
+    rebuild: bool,
+    /// How to compress the persistent binary file: `none`, `deflate`, `lz4`, `zstd` or `zstd:<LEVEL>`, from 1 (fastest) to 22 (smallest).
+    #[arg(long, global = true, default_value_t = Codec::default())]
+    codec: Codec,
+    /// Keep the previous persistent binary file, with a `.bak` extension appended, whenever it is overwritten.
+    #[arg(long, global = true)]
+    backup: bool,
+    /// Neither read nor write the persistent binary file, always extract the streaming data from the export.
+    #[arg(long, global = true, conflicts_with = "rebuild")]
+    no_cache: bool,
+    /// The time zone to bucket and display the streams in: `UTC`, an IANA time zone such as `Europe/Amsterdam`, or `country` to infer it from the country every stream was played in.
+    /// The persistent binary file always stays in UTC.
+    #[arg(long, global = true, default_value_t = TimeZone::default())]
+    tz: TimeZone,
+    /// Only take the streams within this period into account.
+    #[command(flatten)]
+    period: PeriodArgs,
     /// The format to use when presenting the results to the user.
     #[command(subcommand)]
     command: SpotifyStatsCommand,
@@ -147,88 +480,1048 @@ where
     Ok(())
 }
 
-fn init_data(
-    data_path: Option<PathBuf>,
-    compress: bool,
-) -> Result<CompressedEndStreamWithKindContainer> {
-    let streaming_data = if let Some(path) = data_path {
-        if let Result::Ok(data) = CompressedEndStreamWithKindContainer::load_from_file(BIN_PATH) {
-            Ok(data)?
+/// Standard competition ranking of already sorted scores, i.e. ties share a rank and are marked with `=`.
+fn competition_ranks<T, I>(sorted_scores: I) -> Vec<String>
+where
+    T: PartialEq,
+    I: IntoIterator<Item = T>,
+{
+    let scores: Vec<T> = sorted_scores.into_iter().collect();
+    let mut ranks: Vec<usize> = Vec::with_capacity(scores.len());
+    for (index, score) in scores.iter().enumerate() {
+        if index > 0 && scores[index - 1] == *score {
+            ranks.push(ranks[index - 1]);
         } else {
-            CompressedEndStreamWithKindContainer::from_folder_of_json(path)?
+            ranks.push(index + 1);
+        }
+    }
+    ranks
+        .iter()
+        .enumerate()
+        .map(|(index, rank)| {
+            let tied = (index > 0 && ranks[index - 1] == *rank)
+                || ranks.get(index + 1).is_some_and(|x| x == rank);
+            if tied {
+                format!("={rank}")
+            } else {
+                rank.to_string()
+            }
+        })
+        .collect()
+}
+
+fn aggregate_table(
+    table: &mut Table,
+    streaming_data: CompressedEndStreamWithKindContainer,
+    level: Level,
+    count: Option<usize>,
+    reversed: bool,
+    combined: bool,
+) {
+    let mut header = vec!["Rank", "Kind", "Artist"];
+    if level == Level::Album {
+        header.push("Album");
+    }
+    header.extend(["Duration (ms)", "Plays", "Distinct Tracks", "Share"]);
+    table.set_header(header);
+    let aggregated = AggregateContainer::new(streaming_data, level);
+    let groups = if combined {
+        vec![aggregated]
+    } else {
+        aggregated.by_kind().into_values().collect()
+    };
+    for mut group in groups {
+        let group_total = group.total_ms_played();
+        group.sort(reversed);
+        let ranks = competition_ranks(group.0.iter().map(|x| x.total_ms_played));
+        for (rank, entry) in ranks
+            .into_iter()
+            .zip(group.0)
+            .take(count.unwrap_or(usize::MAX))
+        {
+            let share = entry.share(group_total);
+            let mut row = vec![rank, entry.kind.to_string(), entry.artist_or_podcast];
+            row.extend(entry.album_or_show);
+            row.extend([
+                entry.total_ms_played.num_milliseconds().to_string(),
+                entry.play_count.to_string(),
+                entry.distinct_tracks.to_string(),
+                format!("{share:.2}%"),
+            ]);
+            table.add_row(row);
+        }
+    }
+}
+
+/// Saves and loads the streaming data with every codec, keeping the fastest of `BENCHMARK_RUNS`.
+fn benchmark_table(streaming_data: &CompressedEndStreamWithKindContainer) -> Result<Table> {
+    let mut table = Table::new();
+    table.load_preset(ASCII_MARKDOWN);
+    table.set_header(["Codec", "Size (bytes)", "Ratio", "Save (ms)", "Load (ms)"]);
+    let uncompressed_len = streaming_data.to_bytes(Codec::None)?.len();
+    for codec in Codec::ALL {
+        let mut bytes = Vec::new();
+        let mut save_ms = f64::INFINITY;
+        let mut load_ms = f64::INFINITY;
+        for _ in 0..BENCHMARK_RUNS {
+            let start = Instant::now();
+            bytes = streaming_data.to_bytes(codec)?;
+            save_ms = save_ms.min(start.elapsed().as_secs_f64() * 1000.0);
+            let start = Instant::now();
+            CompressedEndStreamWithKindContainer::from_bytes(&bytes)?;
+            load_ms = load_ms.min(start.elapsed().as_secs_f64() * 1000.0);
+        }
+        table.add_row([
+            codec.to_string(),
+            bytes.len().to_string(),
+            format!(
+                "{:.1}%",
+                bytes.len() as f64 / uncompressed_len as f64 * 100.0
+            ),
+            format!("{save_ms:.1}"),
+            format!("{load_ms:.1}"),
+        ]);
+    }
+    Ok(table)
+}
+
+/// Formats a duration as hours and minutes, or minutes and seconds when shorter than an hour.
+fn display_duration(duration: Duration) -> String {
+    let minutes = duration.num_minutes();
+    if minutes >= 60 {
+        format!("{}h {:02}m", minutes / 60, minutes % 60)
+    } else {
+        format!("{minutes}m {:02}s", duration.num_seconds() % 60)
+    }
+}
+
+/// Formats a whole amount of minutes, e.g. `15m` or `2h`.
+fn display_minutes(minutes: i64) -> String {
+    if minutes % 60 == 0 {
+        format!("{}h", minutes / 60)
+    } else {
+        format!("{minutes}m")
+    }
+}
+
+fn display_share(part: i64, total: i64) -> String {
+    if total == 0 {
+        String::new()
+    } else {
+        format!("{:.2}%", part as f64 / total as f64 * 100.0)
+    }
+}
+
+/// Summarizes the sessions, and lists their length distribution, the longest sessions and the platforms they ran on.
+fn sessions_report(sessions: &SessionContainer, count: usize) -> Result<String> {
+    let mut out = String::new();
+    writeln!(out, "Sessions: {}", sessions.len())?;
+    if sessions.is_empty() {
+        return Ok(out);
+    }
+    let total = sessions.total_length();
+    let plays = sessions.plays_per_session();
+    writeln!(out, "Total length: {}", display_duration(total))?;
+    writeln!(
+        out,
+        "Average length: {}",
+        display_duration(total / sessions.len() as i32)
+    )?;
+    writeln!(
+        out,
+        "Tracks per session: {:.1} on average, {} median, {} at most",
+        plays.iter().sum::<usize>() as f64 / plays.len() as f64,
+        plays[plays.len() / 2],
+        plays[plays.len() - 1]
+    )?;
+
+    let bounds = SESSION_LENGTH_BOUNDS_MINUTES.map(Duration::minutes);
+    let mut table = Table::new();
+    table.load_preset(ASCII_MARKDOWN);
+    table.set_header(["Length", "Sessions", "Share"]);
+    for (index, amount) in sessions
+        .length_distribution(&bounds)
+        .into_iter()
+        .enumerate()
+    {
+        let minutes = SESSION_LENGTH_BOUNDS_MINUTES;
+        let label = match (index.checked_sub(1).map(|x| minutes[x]), minutes.get(index)) {
+            (None, Some(upper)) => format!("< {}", display_minutes(*upper)),
+            (Some(lower), Some(upper)) => {
+                format!("{} - {}", display_minutes(lower), display_minutes(*upper))
+            }
+            (Some(lower), None) => format!(">= {}", display_minutes(lower)),
+            (None, None) => String::new(),
+        };
+        table.add_row([
+            label,
+            amount.to_string(),
+            display_share(amount as i64, sessions.len() as i64),
+        ]);
+    }
+    writeln!(out, "\n{table}")?;
+
+    let longest = sessions.longest(count);
+    let mut table = Table::new();
+    table.load_preset(ASCII_MARKDOWN);
+    table.set_header([
+        "Rank", "User", "Start", "End", "Length", "Tracks", "Platform",
+    ]);
+    let ranks = competition_ranks(longest.iter().map(|x| x.length()));
+    for (rank, session) in ranks.into_iter().zip(longest) {
+        table.add_row([
+            rank,
+            session.username.clone(),
+            session.start.format(TIMESTAMP_FORMAT).to_string(),
+            session.end.format(TIMESTAMP_FORMAT).to_string(),
+            display_duration(session.length()),
+            session.plays.len().to_string(),
+            session.platform.clone(),
+        ]);
+    }
+    writeln!(out, "\n{table}")?;
+
+    let mut table = Table::new();
+    table.load_preset(ASCII_MARKDOWN);
+    table.set_header(["Platform", "Sessions", "Length", "Share"]);
+    for (platform, (amount, length)) in sessions.by_platform() {
+        table.add_row([
+            platform.to_string(),
+            amount.to_string(),
+            display_duration(length),
+            display_share(length.num_milliseconds(), total.num_milliseconds()),
+        ]);
+    }
+    write!(out, "\n{table}")?;
+    Ok(out)
+}
+
+/// Draws the heatmap as a grid of shades, the darkest shade is the busiest hour.
+fn shaded_heatmap(heatmap: &Heatmap) -> Result<String> {
+    let mut out = String::new();
+    let max = heatmap.max_ms_played();
+    writeln!(
+        out,
+        "{} (total {}, busiest hour {})",
+        heatmap.label,
+        display_duration(Duration::milliseconds(heatmap.total_ms_played())),
+        display_duration(Duration::milliseconds(max))
+    )?;
+    write!(out, "    ")?;
+    for hour in (0..HOURS).step_by(3) {
+        write!(out, " {hour:02}   ")?;
+    }
+    writeln!(out)?;
+    for (weekday, hours) in WEEKDAY_NAMES.iter().zip(heatmap.ms_played) {
+        write!(out, "{weekday} ")?;
+        for ms_played in hours {
+            // Any listening at all is at least the lightest shade.
+            let level = if max == 0 {
+                0
+            } else {
+                (ms_played * (SHADES.len() as i64 - 1) + max - 1) / max
+            };
+            let shade = SHADES[level as usize];
+            write!(out, "{shade}{shade}")?;
+        }
+        writeln!(out)?;
+    }
+    Ok(out)
+}
+
+/// Lists the heatmaps as CSV, with one row per heatmap, weekday and hour.
+fn heatmap_csv(heatmaps: &HeatmapContainer) -> Result<String> {
+    let mut out = String::new();
+    writeln!(out, "label,weekday,hour,ms_played")?;
+    for heatmap in &heatmaps.0 {
+        let label = format!("\"{}\"", heatmap.label.replace('"', "\"\""));
+        for (weekday, hours) in WEEKDAY_NAMES.iter().zip(heatmap.ms_played) {
+            for (hour, ms_played) in hours.into_iter().enumerate() {
+                writeln!(out, "{label},{weekday},{hour},{ms_played}")?;
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// What the review covers, e.g. `2023` for a whole calendar year.
+fn wrapped_title(range: &DateRange) -> String {
+    if let Some(start) = range.start {
+        if DateRange::year(start.year()).is_ok_and(|x| x == *range) {
+            return start.year().to_string();
+        }
+    }
+    format!(
+        "{} until {}",
+        range.start.map_or("the start".to_string(), |x| x
+            .format(TIMESTAMP_FORMAT)
+            .to_string()),
+        range.end.map_or("now".to_string(), |x| x
+            .format(TIMESTAMP_FORMAT)
+            .to_string())
+    )
+}
+
+/// The headline numbers of the review, as label and value.
+fn wrapped_highlights(wrapped: &Wrapped) -> Vec<(&'static str, String)> {
+    let mut out = vec![
+        (
+            "Minutes listened",
+            display_count(wrapped.total_ms_played.num_minutes()),
+        ),
+        ("Streams", display_count(wrapped.stream_count as i64)),
+        ("Artists", display_count(wrapped.distinct_artists as i64)),
+        ("Tracks", display_count(wrapped.distinct_tracks as i64)),
+    ];
+    if let Some((day, time)) = wrapped.busiest_day {
+        out.push((
+            "Busiest day",
+            format!("{} ({})", day.format("%A %Y-%m-%d"), display_duration(time)),
+        ));
+    }
+    if let Some(streak) = wrapped.longest_streak {
+        out.push((
+            "Longest streak",
+            format!(
+                "{} {} ({} until {})",
+                streak.days(),
+                if streak.days() == 1 { "day" } else { "days" },
+                streak.start,
+                streak.end
+            ),
+        ));
+    }
+    if let Some((track, skips)) = &wrapped.most_skipped {
+        out.push((
+            "Most skipped",
+            format!(
+                "{} by {} ({skips} skips)",
+                track.name,
+                track.artist.as_deref().unwrap_or_default()
+            ),
+        ));
+    }
+    out
+}
+
+fn wrapped_rankings(wrapped: &Wrapped) -> [(&'static str, &[Ranked]); 5] {
+    [
+        ("Top artists", &wrapped.top_artists),
+        ("Top tracks", &wrapped.top_tracks),
+        ("Top albums", &wrapped.top_albums),
+        ("Top podcasts", &wrapped.top_podcasts),
+        ("New discoveries", &wrapped.discoveries),
+    ]
+}
+
+/// Renders the review for the terminal, every ranking is a table with a bar relative to the first entry.
+fn wrapped_report(wrapped: &Wrapped) -> Result<String> {
+    let mut out = String::new();
+    let title = format!("Wrapped {}", wrapped_title(&wrapped.range));
+    writeln!(out, "{title}\n{}\n", "=".repeat(title.chars().count()))?;
+    for (label, value) in wrapped_highlights(wrapped) {
+        writeln!(out, "{:<18}{value}", format!("{label}:"))?;
+    }
+    for (heading, entries) in wrapped_rankings(wrapped) {
+        writeln!(out, "\n{heading}\n")?;
+        if entries.is_empty() {
+            writeln!(out, "Nothing played.")?;
+            continue;
+        }
+        let by_artist = entries.iter().any(|x| x.artist.is_some());
+        let max = entries[0].total_ms_played.num_milliseconds();
+        let mut table = Table::new();
+        table.load_preset(ASCII_MARKDOWN);
+        let mut header = vec!["Rank", "Name"];
+        if by_artist {
+            header.push("Artist");
+        }
+        header.extend(["Time", "Streams", ""]);
+        table.set_header(header);
+        let ranks = competition_ranks(entries.iter().map(|x| x.total_ms_played));
+        for (rank, entry) in ranks.into_iter().zip(entries) {
+            let mut row = vec![rank, entry.name.clone()];
+            if by_artist {
+                row.push(entry.artist.clone().unwrap_or_default());
+            }
+            row.extend([
+                display_duration(entry.total_ms_played),
+                entry.stream_count.to_string(),
+                display_bar(entry.total_ms_played.num_milliseconds(), max),
+            ]);
+            table.add_row(row);
+        }
+        writeln!(out, "{table}")?;
+    }
+
+    writeln!(out, "\nPlatforms\n")?;
+    if wrapped.platforms.is_empty() {
+        write!(out, "Nothing played.")?;
+        return Ok(out);
+    }
+    let total = wrapped.total_ms_played.num_milliseconds();
+    let max = wrapped
+        .platforms
+        .first()
+        .map_or(0, |(_, x)| x.num_milliseconds());
+    let mut table = Table::new();
+    table.load_preset(ASCII_MARKDOWN);
+    table.set_header(["Platform", "Time", "Share", ""]);
+    for (platform, time) in &wrapped.platforms {
+        table.add_row([
+            platform.clone(),
+            display_duration(*time),
+            display_share(time.num_milliseconds(), total),
+            display_bar(time.num_milliseconds(), max),
+        ]);
+    }
+    write!(out, "{table}")?;
+    Ok(out)
+}
+
+/// Renders the review as a single web page, without any external resources.
+fn wrapped_html(wrapped: &Wrapped) -> Result<String> {
+    let mut out = String::new();
+    let title = escape_html(&format!("Wrapped {}", wrapped_title(&wrapped.range)));
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html lang=\"en\">")?;
+    writeln!(out, "<head>")?;
+    writeln!(out, "<meta charset=\"utf-8\">")?;
+    writeln!(
+        out,
+        "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">"
+    )?;
+    writeln!(out, "<title>{title}</title>")?;
+    writeln!(out, "<style>{WRAPPED_CSS}</style>")?;
+    writeln!(out, "</head>")?;
+    writeln!(out, "<body>")?;
+    writeln!(out, "<h1>{title}</h1>")?;
+
+    writeln!(out, "<section class=\"highlights\">")?;
+    for (label, value) in wrapped_highlights(wrapped) {
+        writeln!(
+            out,
+            "<div class=\"card\"><div class=\"label\">{}</div><div class=\"value\">{}</div></div>",
+            escape_html(label),
+            escape_html(&value)
+        )?;
+    }
+    writeln!(out, "</section>")?;
+
+    for (heading, entries) in wrapped_rankings(wrapped) {
+        writeln!(out, "<section>")?;
+        writeln!(out, "<h2>{}</h2>", escape_html(heading))?;
+        if entries.is_empty() {
+            writeln!(out, "<p class=\"empty\">Nothing played.</p>")?;
+            writeln!(out, "</section>")?;
+            continue;
+        }
+        writeln!(out, "<ol>")?;
+        let max = entries
+            .first()
+            .map_or(0, |x| x.total_ms_played.num_milliseconds());
+        for entry in entries {
+            write!(
+                out,
+                "<li><span class=\"name\">{}</span>",
+                escape_html(&entry.name)
+            )?;
+            if let Some(artist) = &entry.artist {
+                write!(
+                    out,
+                    " <span class=\"artist\">{}</span>",
+                    escape_html(artist)
+                )?;
+            }
+            writeln!(
+                out,
+                " <span class=\"time\">{} &middot; {} streams</span>{}</li>",
+                display_duration(entry.total_ms_played),
+                entry.stream_count,
+                html_bar(entry.total_ms_played.num_milliseconds(), max)
+            )?;
+        }
+        writeln!(out, "</ol>")?;
+        writeln!(out, "</section>")?;
+    }
+
+    writeln!(out, "<section>")?;
+    writeln!(out, "<h2>Platforms</h2>")?;
+    if wrapped.platforms.is_empty() {
+        writeln!(out, "<p class=\"empty\">Nothing played.</p>")?;
+    } else {
+        writeln!(out, "<ol>")?;
+    }
+    let total = wrapped.total_ms_played.num_milliseconds();
+    let max = wrapped
+        .platforms
+        .first()
+        .map_or(0, |(_, x)| x.num_milliseconds());
+    for (platform, time) in &wrapped.platforms {
+        writeln!(
+            out,
+            "<li><span class=\"name\">{}</span> <span class=\"time\">{} &middot; {}</span>{}</li>",
+            escape_html(platform),
+            display_duration(*time),
+            display_share(time.num_milliseconds(), total),
+            html_bar(time.num_milliseconds(), max)
+        )?;
+    }
+    if !wrapped.platforms.is_empty() {
+        writeln!(out, "</ol>")?;
+    }
+    writeln!(out, "</section>")?;
+    writeln!(out, "</body>")?;
+    write!(out, "</html>")?;
+    Ok(out)
+}
+
+/// Formats a whole number with thousands separators, e.g. `12,345`.
+fn display_count(count: i64) -> String {
+    let digits = count.unsigned_abs().to_string();
+    let mut out = String::new();
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(digit);
+    }
+    if count < 0 {
+        out.insert(0, '-');
+    }
+    out
+}
+
+/// A horizontal bar of at most `WRAPPED_BAR_WIDTH` characters, any listening at all gets at least one.
+fn display_bar(part: i64, max: i64) -> String {
+    if max <= 0 {
+        return String::new();
+    }
+    let width = WRAPPED_BAR_WIDTH as i64;
+    let filled = (part * width + max - 1) / max;
+    SHADES[SHADES.len() - 1].to_string().repeat(filled as usize)
+}
+
+fn html_bar(part: i64, max: i64) -> String {
+    let percentage = if max <= 0 {
+        0.0
+    } else {
+        part as f64 / max as f64 * 100.0
+    };
+    format!("<div class=\"bar\" style=\"width: {percentage:.1}%\"></div>")
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
         }
+    }
+    out
+}
+
+fn display_optional_timestamp(timestamp: Option<NaiveDateTime>) -> String {
+    timestamp
+        .map(|x| x.format(TIMESTAMP_FORMAT).to_string())
+        .unwrap_or_default()
+}
+
+/// Whether the path is a downloaded `.zip` archive, rather than an unpacked folder.
+fn is_zip(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|x| x.eq_ignore_ascii_case("zip"))
+}
+
+/// Reads the streaming data from either an unpacked folder or the downloaded `.zip` archive.
+///
+/// Every record is compressed as soon as it is read, so the export is never held in memory as a whole.
+fn read_compressed_data(
+    path: PathBuf,
+    options: &ImportOptions,
+) -> Result<CompressedEndStreamWithKindContainer> {
+    let (streaming_data, report) = if is_zip(&path) {
+        CompressedEndStreamWithKindContainer::read_zip(path, options)?
     } else {
-        panic!("Provide `--data <FOLDER>` to init data from")
+        CompressedEndStreamWithKindContainer::read_folder(path, options)?
     };
-    streaming_data.save_to_file(BIN_PATH, compress)?;
+    eprint!("{report}");
     Ok(streaming_data)
 }
 
-pub const INITIAL_VEC_CAP: usize = 128;
+fn is_newer_version(error: &spotify_stats::Error) -> bool {
+    matches!(
+        error,
+        spotify_stats::Error::Format(FormatError::NewerVersion { .. })
+    )
+}
+
+/// How the persistent cache is used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CachePolicy {
+    /// Use the cache when it was built from the same export, rebuild it otherwise.
+    Use,
+    /// Always rebuild the cache.
+    Rebuild,
+    /// Neither read nor write the cache.
+    Ignore,
+}
+
+/// Where the persistent cache is stored, and how it is used.
+#[derive(Debug, Clone)]
+struct CacheSettings {
+    path: PathBuf,
+    policy: CachePolicy,
+    /// Keep the previous file when overwriting it.
+    backup: bool,
+    codec: Codec,
+}
+
+/// Loads the cache, `None` when it is missing or unreadable.
+fn load_cache(cache_path: &Path) -> Result<Option<Cache>> {
+    match Cache::load_from_file(cache_path) {
+        Result::Ok(cache) => Ok(Some(cache)),
+        // Never overwrite a file written by a newer version of this tool.
+        Err(error) if is_newer_version(&error) => Err(error.into()),
+        Err(error) => {
+            if cache_path.exists() {
+                eprintln!(
+                    "warning: could not load `{}`, ignoring it: {error}",
+                    cache_path.display()
+                );
+            }
+            Ok(None)
+        }
+    }
+}
+
+/// Saves the cache, creating its directory when needed.
+fn save_cache(cache: &Cache, settings: &CacheSettings) -> Result<()> {
+    if let Some(parent) = settings.path.parent() {
+        create_dir_all(parent)?;
+    }
+    if settings.backup {
+        cache.save_to_file_with_backup(&settings.path, settings.codec)?
+    } else {
+        cache.save_to_file(&settings.path, settings.codec)?
+    }
+    Ok(())
+}
+
+/// Loads the persistent binary file, checks its header and the consistency of its content, and reports on it.
+fn verify_cache(path: &Path) -> Result<()> {
+    let mut bytes = Vec::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut bytes))
+        .wrap_err_with(|| format!("Could not read `{}`", path.display()))?;
+    let header = Header::split(&bytes)?.map(|(header, _payload)| header);
+    let cache = Cache::from_bytes(&bytes)?;
+    println!("{}", path.display());
+    match header {
+        Some(header) => {
+            println!("  format version: {}", header.version);
+            println!("  codec:          {}", header.codec);
+            println!("  checksum:       {:#010x}, ok", header.checksum);
+        }
+        None => println!("  format version: 0, without header or checksum"),
+    }
+    println!("  size:           {} bytes", bytes.len());
+    println!("  exports:        {}", cache.sources.len());
+    let problems = cache.verify();
+    for problem in &problems {
+        println!("  error: {problem}");
+    }
+    if problems.is_empty() {
+        println!("  content:        ok");
+        Ok(())
+    } else {
+        Err(spotify_stats::Error::Integrity(problems))
+            .wrap_err_with(|| format!("`{}` is inconsistent", path.display()))
+    }
+}
+
+/// Merges a new export into the persistent binary file, creating it if it doesn't exist yet.
+fn import_data(path: PathBuf, settings: &CacheSettings, options: &ImportOptions) -> Result<()> {
+    let exists = settings.path.exists();
+    let mut cache = match settings.policy {
+        // The cache holds every export merged before, so never start over from scratch implicitly.
+        CachePolicy::Use if exists => {
+            Cache::load_from_file(&settings.path).wrap_err_with(|| {
+                format!(
+                    "Could not load `{}`, refusing to overwrite the exports merged into it, pass `--rebuild` to start over from this export",
+                    settings.path.display()
+                )
+            })?
+        }
+        CachePolicy::Use | CachePolicy::Rebuild => Cache::default(),
+        CachePolicy::Ignore => {
+            return Err(eyre!(
+                "`import` writes to the cache, so it can't be combined with `--no-cache`"
+            ))
+        }
+    };
+    let fingerprint = SourceFingerprint::new(&path)?;
+    let (import_report, merge_report) = cache.data.merge_stream(|sink| {
+        if is_zip(&path) {
+            stream_zip(&path, options, sink)
+        } else {
+            stream_folder(&path, options, sink)
+        }
+    });
+    eprint!("{}", import_report?);
+    eprint!("{merge_report}");
+    cache.add_source(fingerprint);
+    // Starting over discards the exports merged before, so always keep the previous file.
+    let settings = CacheSettings {
+        backup: settings.backup || (settings.policy == CachePolicy::Rebuild && exists),
+        ..settings.clone()
+    };
+    save_cache(&cache, &settings)?;
+    Ok(())
+}
+
+fn init_data(
+    data_path: Option<PathBuf>,
+    settings: &CacheSettings,
+    options: &ImportOptions,
+) -> Result<CompressedEndStreamWithKindContainer> {
+    let Some(path) = data_path else {
+        if settings.policy != CachePolicy::Use {
+            return Err(eyre!(
+                "`--rebuild` and `--no-cache` need `--data <FOLDER>` to extract the streaming data from"
+            ));
+        }
+        return match load_cache(&settings.path)? {
+            Some(cache) => Ok(cache.data),
+            None => Err(eyre!(
+                "No streaming data found at `{}`, provide `--data <FOLDER>` to init data from",
+                settings.path.display()
+            )),
+        };
+    };
+    let fingerprint = SourceFingerprint::new(&path)?;
+    let exists = settings.path.exists();
+    if settings.policy == CachePolicy::Use {
+        if let Some(cache) = load_cache(&settings.path)? {
+            if cache.is_built_from(&fingerprint) {
+                return Ok(cache.data);
+            }
+            // Rebuilding from this export alone would discard the other exports merged with `import`.
+            if cache.sources.len() > 1 {
+                return Err(eyre!(
+                    "`{}` holds {} exports merged with `import`, and `{}` is not one of them in its current state, run `import {}` to merge it, or pass `--rebuild` to start over from this export",
+                    settings.path.display(),
+                    cache.sources.len(),
+                    path.display(),
+                    path.display()
+                ));
+            }
+            eprintln!(
+                "`{}` was not built from `{}`, or it changed since, rebuilding it.",
+                settings.path.display(),
+                path.display()
+            );
+        }
+    }
+    let streaming_data = read_compressed_data(path, options)?;
+    if settings.policy == CachePolicy::Ignore {
+        return Ok(streaming_data);
+    }
+    let cache = Cache::new(fingerprint, streaming_data);
+    // Rebuilding discards whatever the file held before, so always keep the previous file.
+    let settings = CacheSettings {
+        backup: settings.backup || exists,
+        ..settings.clone()
+    };
+    save_cache(&cache, &settings)?;
+    Ok(cache.data)
+}
 
+/// From no listening at all to the busiest hour.
+pub const SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+pub const WEEKDAY_NAMES: [&str; WEEKDAYS] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+/// The upper bounds of the session lengths in the length distribution.
+pub const SESSION_LENGTH_BOUNDS_MINUTES: [i64; 5] = [15, 30, 60, 120, 240];
+/// The width of the bars in the rankings of the review.
+pub const WRAPPED_BAR_WIDTH: usize = 20;
+/// The styling of the review as a web page.
+pub const WRAPPED_CSS: &str = "
+body { margin: 0 auto; max-width: 56rem; padding: 2rem; font-family: system-ui, sans-serif; background: #121212; color: #fff; }
+h1 { font-size: 3rem; color: #1ed760; }
+h2 { border-bottom: 1px solid #333; padding-bottom: 0.5rem; }
+.highlights { display: grid; grid-template-columns: repeat(auto-fill, minmax(14rem, 1fr)); gap: 1rem; }
+.card { background: #1f1f1f; border-radius: 0.5rem; padding: 1rem; }
+.label { color: #b3b3b3; font-size: 0.9rem; }
+.value { font-size: 1.3rem; font-weight: bold; margin-top: 0.3rem; }
+ol { padding-left: 1.5rem; }
+li { margin: 0.8rem 0; }
+.name { font-weight: bold; }
+.artist, .time, .empty { color: #b3b3b3; }
+.time { float: right; }
+.bar { height: 0.3rem; margin-top: 0.3rem; border-radius: 0.15rem; background: #1ed760; }
+";
+/// How often every codec is benchmarked.
+pub const BENCHMARK_RUNS: usize = 3;
+/// The cache, when the data directory of the user is unknown.
 pub const BIN_PATH: &str = "spotify_stats.bin";
+/// The profile used when none is given.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// The cache of the profile, in the data directory of the user, unless overridden.
+fn cache_path(cache: Option<PathBuf>, profile: &str) -> PathBuf {
+    match (cache, dirs::data_dir()) {
+        (Some(path), _) => path,
+        (None, Some(data_dir)) => data_dir
+            .join("spotify_stats")
+            .join(format!("{profile}.bin")),
+        (None, None) if profile == DEFAULT_PROFILE => PathBuf::from(BIN_PATH),
+        (None, None) => PathBuf::from(format!("spotify_stats_{profile}.bin")),
+    }
+}
+
+pub const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
 
 fn main() -> Result<()> {
     let args = SpotifyStats::parse();
-    let streaming_data = init_data(args.data, true)?;
+    let options = ImportOptions {
+        lenient: args.lenient,
+        parallel: !args.serial,
+    };
+    let policy = if args.no_cache {
+        CachePolicy::Ignore
+    } else if args.rebuild {
+        CachePolicy::Rebuild
+    } else {
+        CachePolicy::Use
+    };
+    let settings = CacheSettings {
+        path: cache_path(args.cache, &args.profile),
+        policy,
+        backup: args.backup,
+        codec: args.codec,
+    };
+    let tz = args.tz;
+    // The streams are in local time, so the periods are measured from the local time as well.
+    let now = tz.now();
+    let range = args.period.range(now)?;
+    let data = args.data;
+    let load = |range: &DateRange| -> Result<CompressedEndStreamWithKindContainer> {
+        Ok(init_data(data, &settings, &options)?
+            .to_local_time(&tz)
+            .filter_dates(range))
+    };
     match args.command {
-        SpotifyStatsCommand::Raw { file, mode } => match mode {
-            RawFormat::Rust { pretty } => deligate_output_debug(file, &streaming_data, pretty)?,
-            RawFormat::Json { pretty } => {
-                if pretty {
-                    deligate_output_display(file, &serde_json::to_string_pretty(&streaming_data)?)?
-                } else {
-                    deligate_output_display(file, &serde_json::to_string(&streaming_data)?)?
+        SpotifyStatsCommand::Import { path } => import_data(path, &settings, &options)?,
+        SpotifyStatsCommand::Sessions { file, gap, count } => {
+            let sessions = SessionContainer::new(load(&range)?, gap);
+            deligate_output_display(file, &sessions_report(&sessions, count)?)?
+        }
+        SpotifyStatsCommand::Heatmap {
+            file,
+            by,
+            count,
+            format,
+        } => {
+            let mut heatmaps = HeatmapContainer::new(load(&range)?, by.map(HeatmapSplit::from));
+            heatmaps.0.truncate(count.unwrap_or(usize::MAX));
+            match format {
+                HeatmapFormat::Shaded => {
+                    let shaded = heatmaps
+                        .0
+                        .iter()
+                        .map(shaded_heatmap)
+                        .collect::<Result<Vec<_>>>()?;
+                    deligate_output_display(file, &shaded.join("\n"))?
+                }
+                HeatmapFormat::Csv => deligate_output_display(file, &heatmap_csv(&heatmaps)?)?,
+                HeatmapFormat::Json => {
+                    deligate_output_display(file, &serde_json::to_string_pretty(&heatmaps)?)?
                 }
             }
-            RawFormat::Bin { compression } => {
-                let bytes = streaming_data.to_bytes(compression)?;
-                deligate_output_display(file, &bytes.escape_ascii())?;
+        }
+        SpotifyStatsCommand::Wrapped {
+            file,
+            count,
+            format,
+        } => {
+            let range = if range.is_unbounded() {
+                DateRange::year(now.year())?
+            } else {
+                range
+            };
+            let wrapped = Wrapped::new(load(&DateRange::default())?, &range, count);
+            let output = match format {
+                WrappedFormat::Text => wrapped_report(&wrapped)?,
+                WrappedFormat::Html => wrapped_html(&wrapped)?,
+            };
+            deligate_output_display(file, &output)?
+        }
+        SpotifyStatsCommand::Benchmark { file } => {
+            deligate_output_display(file, &benchmark_table(&load(&range)?)?)?
+        }
+        SpotifyStatsCommand::Verify { path } => {
+            verify_cache(path.as_deref().unwrap_or(&settings.path))?
+        }
+        SpotifyStatsCommand::Raw { file, mode } => {
+            let streaming_data = load(&range)?;
+            match mode {
+                RawFormat::Rust { pretty } => deligate_output_debug(file, &streaming_data, pretty)?,
+                RawFormat::Json { pretty } => {
+                    if pretty {
+                        deligate_output_display(
+                            file,
+                            &serde_json::to_string_pretty(&streaming_data)?,
+                        )?
+                    } else {
+                        deligate_output_display(file, &serde_json::to_string(&streaming_data)?)?
+                    }
+                }
+                RawFormat::Bin { compression } => {
+                    let codec = if compression {
+                        settings.codec
+                    } else {
+                        Codec::None
+                    };
+                    let bytes = streaming_data.to_bytes(codec)?;
+                    deligate_output_display(file, &bytes.escape_ascii())?;
+                }
             }
-        },
+        }
         SpotifyStatsCommand::Table {
             file,
             format,
-            artist: _,
-            album: _,
-            track: _,
+            artist,
+            album,
+            track,
+            mode,
         } => {
+            let filter = Filter::new(
+                artist.as_deref(),
+                album.as_deref(),
+                track.as_deref(),
+                mode.into(),
+            )?;
+            let streaming_data = load(&range)?.filter(&filter);
             let mut table = Table::new();
             table.load_preset(ASCII_MARKDOWN);
             match format {
-                Format::Sort { count, reversed } => {
-                    let mut counter = 1;
-                    let mut cleaned_entries =
-                        EndStreamKindCompressedLogContainer::from(streaming_data);
+                Format::Sort {
+                    count,
+                    reversed,
+                    split_by,
+                    by,
+                    threshold,
+                } => {
+                    let threshold = Duration::milliseconds(threshold);
+                    let dimensions: Vec<Dimension> =
+                        split_by.iter().copied().map(Dimension::from).collect();
+                    let cleaned_entries = EndStreamKindCompressedLogContainer::from(streaming_data)
+                        .merge(&dimensions);
+                    let mut scored_entries: Vec<_> = cleaned_entries
+                        .0
+                        .into_iter()
+                        .map(|x| (x.log.stream_count(threshold), x))
+                        .filter(|(plays, _)| by == SortBy::Duration || *plays > 0)
+                        .collect();
+                    let score = |(plays, entry): &(usize, EndStreamKindCompressedLog)| match by {
+                        SortBy::Duration => entry.total_ms_played.num_milliseconds(),
+                        SortBy::Plays => *plays as i64,
+                    };
                     if reversed {
-                        cleaned_entries
-                            .0
-                            .sort_by(|a, b| a.total_ms_played.cmp(&b.total_ms_played))
+                        scored_entries.sort_by_key(score)
                     } else {
-                        cleaned_entries
-                            .0
-                            .sort_by(|a, b| a.total_ms_played.cmp(&b.total_ms_played).reverse())
+                        scored_entries.sort_by_key(|x| std::cmp::Reverse(score(x)))
                     };
-                    table.set_header(["Rank", "Artist", "Album", "Track", "Duration (ms)"]);
-                    for cleaned_entry in cleaned_entries
-                        .0
-                        .iter()
-                        .take(count.unwrap_or(cleaned_entries.0.len()))
+                    let mut header = vec!["Rank"];
+                    if split_by.contains(&SplitBy::Username) {
+                        header.push("Username");
+                    }
+                    if split_by.contains(&SplitBy::Country) {
+                        header.push("Country");
+                    }
+                    if split_by.contains(&SplitBy::Platform) {
+                        header.push("Platform");
+                    }
+                    header.extend(["Kind", "Artist", "Album", "Track", "Duration (ms)", "Plays"]);
+                    table.set_header(header);
+                    let ranks = competition_ranks(scored_entries.iter().map(score));
+                    for (rank, (plays, cleaned_entry)) in ranks
+                        .into_iter()
+                        .zip(scored_entries)
+                        .take(count.unwrap_or(usize::MAX))
                     {
-                        table.add_row([
-                            counter.to_string(),
-                            cleaned_entry.artist_or_podcast.clone(),
-                            cleaned_entry.album_or_show.clone(),
-                            cleaned_entry.track_or_episode.clone(),
+                        let mut row = vec![rank];
+                        row.extend(cleaned_entry.username);
+                        row.extend(cleaned_entry.conn_country);
+                        row.extend(cleaned_entry.platform);
+                        row.extend([
+                            cleaned_entry.kind.to_string(),
+                            cleaned_entry.artist_or_podcast,
+                            cleaned_entry.album_or_show,
+                            cleaned_entry.track_or_episode,
                             cleaned_entry.total_ms_played.num_milliseconds().to_string(),
+                            plays.to_string(),
                         ]);
-                        counter += 1;
+                        table.add_row(row);
                     }
                 }
                 Format::Lex => {
-                    table.set_header(["Artist", "Album", "Track", "Duration (ms)"]);
-                    todo!()
+                    table.set_header([
+                        "Kind",
+                        "Artist",
+                        "Album",
+                        "Track",
+                        "Duration (ms)",
+                        "Plays",
+                        "First Play",
+                        "Last Play",
+                    ]);
+                    for (kind, entries) in streaming_data.by_kind() {
+                        for (artist, album, track, info) in entries {
+                            table.add_row([
+                                kind.to_string(),
+                                artist,
+                                album,
+                                track,
+                                info.total_ms_played.num_milliseconds().to_string(),
+                                info.play_count().to_string(),
+                                display_optional_timestamp(info.first_play()),
+                                display_optional_timestamp(info.last_play()),
+                            ]);
+                        }
+                    }
                 }
+                Format::Artists {
+                    count,
+                    reversed,
+                    combined,
+                } => aggregate_table(
+                    &mut table,
+                    streaming_data,
+                    Level::Artist,
+                    count,
+                    reversed,
+                    combined,
+                ),
+                Format::Albums {
+                    count,
+                    reversed,
+                    combined,
+                } => aggregate_table(
+                    &mut table,
+                    streaming_data,
+                    Level::Album,
+                    count,
+                    reversed,
+                    combined,
+                ),
             };
             deligate_output_display(file, &table)?;
         }
@@ -6,6 +6,7 @@
 // };
 
 // const DATA_FOLDER: &str = "full_data";
+const LEGACY_DATA_FOLDER: &str = "legacy_data";
 
 // /// <JSON FOLDER> -> [RawStreamingData] -> <BYTES> -> [RawStreamingData]
 // ///                          |                                |
@@ -135,3 +136,750 @@
 //     assert_eq!(initial_cleaned, secondary_cleaned);
 //     Ok(())
 // }
+
+use std::error::Error;
+
+use spotify_stats::model::{
+    backup_path,
+    cache::{Cache, SourceFingerprint},
+    codec::Codec,
+    compression::CompressedEndStreamWithKindContainer,
+    end_stream::{
+        stream_folder, EndStream, EndStreamKind, EndStreamWithKind, EndStreamWithKindContainer,
+    },
+    filter::DateRange,
+    heatmap::Heatmap,
+    import::ImportOptions,
+    legacy::LegacyStreamContainer,
+    session::SessionContainer,
+    timezone::TimeZone,
+    wrapped::Wrapped,
+    FormatError, Header, Persist,
+};
+
+const DATA_FOLDER: &str = "full_data";
+
+#[test]
+fn test_competition_ranks_share_rank_on_ties() {
+    let ranks = super::competition_ranks([9, 7, 7, 5, 3, 3, 3]);
+    assert_eq!(ranks, ["1", "=2", "=2", "4", "=5", "=5", "=5"]);
+}
+
+#[test]
+fn test_merge_same_export_twice_only_adds_once() -> Result<(), Box<dyn Error>> {
+    let (records, _) = EndStreamWithKindContainer::read_folder(DATA_FOLDER, &Default::default())?;
+    let mut merged = CompressedEndStreamWithKindContainer::default();
+    let first = merged.merge(records.clone());
+    let after_first = merged.clone();
+    let second = merged.merge(records);
+    assert_eq!(second.new, 0);
+    assert_eq!(second.duplicate, first.new + first.duplicate);
+    assert_eq!(merged, after_first);
+    Ok(())
+}
+
+#[test]
+fn test_streamed_merge_matches_buffered_merge() -> Result<(), Box<dyn Error>> {
+    let (records, _) = EndStreamWithKindContainer::read_folder(DATA_FOLDER, &Default::default())?;
+    let mut buffered = CompressedEndStreamWithKindContainer::default();
+    let mut streamed = CompressedEndStreamWithKindContainer::default();
+    for _ in 0..2 {
+        let expected = buffered.merge(records.clone());
+        let (import_report, report) =
+            streamed.merge_stream(|sink| stream_folder(DATA_FOLDER, &Default::default(), sink));
+        assert_eq!(import_report?.total_records(), records.0.len());
+        assert_eq!(report, expected);
+        assert_eq!(streamed, buffered);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_streams_ending_in_the_same_second_are_all_kept() -> Result<(), Box<dyn Error>> {
+    let (records, _) = EndStreamWithKindContainer::read_folder(DATA_FOLDER, &Default::default())?;
+    let complete = records.0.iter().filter(|x| x.names().is_some()).count();
+    let compressed = CompressedEndStreamWithKindContainer::from(records);
+    let logged: usize = compressed
+        .into_iter()
+        .map(|(_, _, _, _, _, _, _, info)| info.play_count())
+        .sum();
+    assert_eq!(logged, complete);
+    Ok(())
+}
+
+#[test]
+fn test_persist_rejects_newer_format_version() -> Result<(), Box<dyn Error>> {
+    let (records, _) = EndStreamWithKindContainer::read_folder(DATA_FOLDER, &Default::default())?;
+    let initial = CompressedEndStreamWithKindContainer::from(records);
+    let mut bytes = initial.to_bytes(Codec::Deflate)?;
+    assert_eq!(
+        CompressedEndStreamWithKindContainer::from_bytes(&bytes)?,
+        initial
+    );
+    bytes[4..6]
+        .copy_from_slice(&(CompressedEndStreamWithKindContainer::FORMAT_VERSION + 1).to_le_bytes());
+    let error = CompressedEndStreamWithKindContainer::from_bytes(&bytes).unwrap_err();
+    assert!(matches!(
+        error,
+        spotify_stats::Error::Format(FormatError::NewerVersion { .. })
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_cache_reads_files_without_sources() -> Result<(), Box<dyn Error>> {
+    let (records, _) = EndStreamWithKindContainer::read_folder(DATA_FOLDER, &Default::default())?;
+    let data = CompressedEndStreamWithKindContainer::from(records);
+    let cache = Cache::from_bytes(&data.to_bytes(Codec::Deflate)?)?;
+    assert!(cache.sources.is_empty());
+    assert_eq!(cache.data, data);
+
+    let fingerprint = SourceFingerprint::new(DATA_FOLDER)?;
+    let cache =
+        Cache::from_bytes(&Cache::new(fingerprint.clone(), data).to_bytes(Codec::Deflate)?)?;
+    assert!(cache.is_built_from(&fingerprint));
+    Ok(())
+}
+
+#[test]
+fn test_save_keeps_a_backup_and_no_temporary_file() -> Result<(), Box<dyn Error>> {
+    let folder = std::env::temp_dir().join(format!("spotify_stats_test_{}", std::process::id()));
+    std::fs::create_dir_all(&folder)?;
+    let path = folder.join("cache.bin");
+    let first = Cache::default();
+    first.save_to_file(&path, Codec::default())?;
+    let (records, _) = EndStreamWithKindContainer::read_folder(DATA_FOLDER, &Default::default())?;
+    let second = Cache::new(
+        SourceFingerprint::new(DATA_FOLDER)?,
+        CompressedEndStreamWithKindContainer::from(records),
+    );
+    second.save_to_file_with_backup(&path, Codec::default())?;
+
+    assert_eq!(Cache::load_from_file(&path)?, second);
+    assert_eq!(Cache::load_from_file(backup_path(&path))?, first);
+    assert!(!folder.join("cache.bin.tmp").exists());
+    std::fs::remove_dir_all(folder)?;
+    Ok(())
+}
+
+#[test]
+fn test_every_codec_round_trips() -> Result<(), Box<dyn Error>> {
+    let (records, _) = EndStreamWithKindContainer::read_folder(DATA_FOLDER, &Default::default())?;
+    let initial = CompressedEndStreamWithKindContainer::from(records);
+    for codec in Codec::ALL {
+        let bytes = initial.to_bytes(codec)?;
+        assert_eq!(
+            Header::split(&bytes)?.map(|(header, _)| header.codec),
+            Some(codec)
+        );
+        assert_eq!(
+            CompressedEndStreamWithKindContainer::from_bytes(&bytes)?,
+            initial
+        );
+        assert_eq!(codec.to_string().parse::<Codec>()?, codec);
+    }
+    for level in ["zstd:0", "zstd:-5", "zstd:23", "zstd:300", "zstd:fast"] {
+        assert!(level.parse::<Codec>().is_err(), "{level}");
+    }
+    assert!(initial.to_bytes(Codec::Zstd { level: 300 }).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_streaming_import_matches_buffered_import() -> Result<(), Box<dyn Error>> {
+    let (records, buffered_report) =
+        EndStreamWithKindContainer::read_folder(DATA_FOLDER, &Default::default())?;
+    let (streamed, streamed_report) =
+        CompressedEndStreamWithKindContainer::read_folder(DATA_FOLDER, &Default::default())?;
+    assert_eq!(
+        streamed,
+        CompressedEndStreamWithKindContainer::from(records)
+    );
+    assert_eq!(streamed_report, buffered_report);
+    Ok(())
+}
+
+#[test]
+fn test_parallel_import_matches_serial_import() -> Result<(), Box<dyn Error>> {
+    // Split the export over several files, so there is something to parallelize.
+    let folder =
+        std::env::temp_dir().join(format!("spotify_stats_parallel_{}", std::process::id()));
+    std::fs::create_dir_all(&folder)?;
+    let content = std::fs::read_to_string(format!(
+        "{DATA_FOLDER}/Spotify Extended Streaming History/Streaming_History_Video_2018-2023.json"
+    ))?;
+    let records: Vec<serde_json::Value> = serde_json::from_str(&content)?;
+    for (index, chunk) in records.chunks(records.len() / 4 + 1).enumerate() {
+        std::fs::write(
+            folder.join(format!("Streaming_History_Audio_2023_{index}.json")),
+            serde_json::to_string(chunk)?,
+        )?;
+    }
+    // The same streams twice, so entries sharing a timestamp have to keep their order.
+    std::fs::write(folder.join("Streaming_History_Audio_2024_0.json"), &content)?;
+
+    let serial = CompressedEndStreamWithKindContainer::read_folder(&folder, &Default::default())?;
+    let parallel = CompressedEndStreamWithKindContainer::read_folder(
+        &folder,
+        &ImportOptions {
+            parallel: true,
+            ..Default::default()
+        },
+    )?;
+    std::fs::remove_dir_all(folder)?;
+    assert_eq!(parallel, serial);
+    Ok(())
+}
+
+#[test]
+fn test_sessions_are_separated_by_more_than_the_idle_gap() -> Result<(), Box<dyn Error>> {
+    let (records, _) = EndStreamWithKindContainer::read_folder(DATA_FOLDER, &Default::default())?;
+    let data = CompressedEndStreamWithKindContainer::from(records);
+    let streams: usize = data.clone().into_iter().map(|x| x.7.play_count()).sum();
+    let gap = chrono::Duration::minutes(30);
+    let sessions = SessionContainer::new(data, gap);
+
+    let plays: usize = sessions.0.iter().map(|x| x.plays.len()).sum();
+    assert_eq!(plays, streams);
+    for pair in sessions.0.windows(2) {
+        if pair[0].username == pair[1].username {
+            assert!(pair[1].start - pair[0].end > gap);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_heatmap_spreads_a_stream_over_the_hours_it_spans() -> Result<(), Box<dyn Error>> {
+    let mut heatmap = Heatmap::new("All".to_string());
+    // From Sunday 23:50 until Monday 01:10.
+    let start = chrono::NaiveDate::from_ymd_opt(2023, 4, 2)
+        .and_then(|x| x.and_hms_opt(23, 50, 0))
+        .ok_or("invalid date")?;
+    heatmap.add(start, start + chrono::Duration::minutes(80));
+    let minute = 60_000;
+    assert_eq!(heatmap.ms_played[6][23], 10 * minute);
+    assert_eq!(heatmap.ms_played[0][0], 60 * minute);
+    assert_eq!(heatmap.ms_played[0][1], 10 * minute);
+    assert_eq!(heatmap.total_ms_played(), 80 * minute);
+    Ok(())
+}
+
+#[test]
+fn test_time_zone_converts_utc_to_local_time() -> Result<(), Box<dyn Error>> {
+    let utc = chrono::NaiveDate::from_ymd_opt(2023, 7, 1)
+        .and_then(|x| x.and_hms_opt(22, 30, 0))
+        .ok_or("invalid date")?;
+    let amsterdam: TimeZone = "Europe/Amsterdam".parse()?;
+    let summer_time = utc + chrono::Duration::hours(2);
+    assert_eq!(amsterdam.to_local(utc, "JP"), summer_time);
+    assert_eq!(TimeZone::FromCountry.to_local(utc, "NL"), summer_time);
+    assert_eq!(TimeZone::FromCountry.to_local(utc, "ZZ"), utc);
+    assert_eq!(TimeZone::Utc.to_local(utc, "NL"), utc);
+    assert!("Mars/Olympus_Mons".parse::<TimeZone>().is_err());
+    // Tokyo has no daylight saving time, so it is always nine hours ahead.
+    let tokyo: TimeZone = "Asia/Tokyo".parse()?;
+    let offset = tokyo.now() - TimeZone::Utc.now();
+    assert!((offset - chrono::Duration::hours(9)).num_seconds().abs() < 5);
+    Ok(())
+}
+
+#[test]
+fn test_wrapped_only_discovers_artists_new_to_the_year() -> Result<(), Box<dyn Error>> {
+    let (records, _) = EndStreamWithKindContainer::read_folder(DATA_FOLDER, &Default::default())?;
+    let data = CompressedEndStreamWithKindContainer::from(records);
+    let year = DateRange::year(2022)?;
+    let wrapped = Wrapped::new(data.clone(), &year, usize::MAX);
+
+    let in_year = data.clone().filter_dates(&year);
+    let total = in_year
+        .into_iter()
+        .fold(chrono::Duration::zero(), |acc, x| acc + x.7.total_ms_played);
+    assert_eq!(wrapped.total_ms_played, total);
+
+    let before = data.filter_dates(&DateRange::new(None, year.start));
+    let known: Vec<String> = before
+        .into_iter()
+        .filter(|x| x.3 == EndStreamKind::EndSong)
+        .map(|x| x.4)
+        .collect();
+    let new = wrapped
+        .top_artists
+        .iter()
+        .filter(|x| !known.contains(&x.name))
+        .count();
+    assert!(new > 0);
+    assert_eq!(wrapped.discoveries.len(), new);
+    assert!(wrapped.discoveries.iter().all(|x| !known.contains(&x.name)));
+    Ok(())
+}
+
+/// A single record of the extended streaming history, with only the fields that matter for the tests.
+fn extended_record(ts: &str, artist: &str, track: &str, uri: &str) -> serde_json::Value {
+    serde_json::json!({
+        "ts": ts,
+        "username": "user",
+        "platform": "android",
+        "ms_played": 120000,
+        "conn_country": "NL",
+        "master_metadata_track_name": track,
+        "master_metadata_album_artist_name": artist,
+        "master_metadata_album_album_name": track,
+        "spotify_track_uri": uri,
+        "skipped": false,
+    })
+}
+
+fn extended_records(
+    values: Vec<serde_json::Value>,
+) -> Result<EndStreamWithKindContainer, Box<dyn Error>> {
+    let streams: Vec<EndStream> = serde_json::from_value(serde_json::Value::Array(values))?;
+    Ok(EndStreamWithKindContainer(
+        streams.into_iter().map(EndStreamWithKind::from).collect(),
+    ))
+}
+
+#[test]
+fn test_merge_tracks_with_several_uris_twice_only_adds_once() -> Result<(), Box<dyn Error>> {
+    // An explicit and a clean version share their names, but not their URI.
+    let export = extended_records(vec![
+        extended_record(
+            "2023-01-01T12:00:00Z",
+            "Artist",
+            "Song",
+            "spotify:track:explicit",
+        ),
+        extended_record(
+            "2023-01-01T12:05:00Z",
+            "Artist",
+            "Song",
+            "spotify:track:clean",
+        ),
+    ])?;
+    let mut merged = CompressedEndStreamWithKindContainer::default();
+    let first = merged.merge(export.clone());
+    assert_eq!(first.new, 2);
+    let second = merged.merge(export);
+    assert_eq!(second.new, 0);
+    assert_eq!(second.duplicate, 2);
+    let plays: usize = merged.into_iter().map(|x| x.7.play_count()).sum();
+    assert_eq!(plays, 2);
+    Ok(())
+}
+
+/// Writes the files into a new temporary folder, which the test has to remove.
+fn temporary_export(
+    name: &str,
+    files: &[(&str, serde_json::Value)],
+) -> Result<std::path::PathBuf, Box<dyn Error>> {
+    let folder = std::env::temp_dir().join(format!("spotify_stats_{name}_{}", std::process::id()));
+    std::fs::create_dir_all(&folder)?;
+    for (file_name, content) in files {
+        let path = folder.join(file_name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string(content)?)?;
+    }
+    Ok(folder)
+}
+
+fn legacy_music_record(end_time: &str) -> serde_json::Value {
+    serde_json::json!({
+        "endTime": end_time,
+        "artistName": "Artist",
+        "trackName": "Song",
+        "msPlayed": 120000,
+    })
+}
+
+#[test]
+fn test_malformed_record_names_file_index_and_field() -> Result<(), Box<dyn Error>> {
+    let cases = [
+        (
+            "Streaming_History_Audio_2023.json",
+            serde_json::json!([
+                extended_record("2023-01-01T12:00:00Z", "Artist", "Song", "spotify:track:a"),
+                extended_record("yesterday", "Artist", "Song", "spotify:track:a"),
+            ]),
+            "ts",
+        ),
+        (
+            "StreamingHistory_music_0.json",
+            serde_json::json!([
+                legacy_music_record("2023-01-01 12:00"),
+                legacy_music_record("yesterday"),
+            ]),
+            "endTime",
+        ),
+    ];
+    for (file_name, content, field) in cases {
+        let folder = temporary_export("malformed", &[(file_name, content)])?;
+        let result =
+            CompressedEndStreamWithKindContainer::read_folder(&folder, &Default::default());
+        std::fs::remove_dir_all(&folder)?;
+        let Err(spotify_stats::Error::Record(record_error)) = result else {
+            panic!("expected a record error for `{file_name}`");
+        };
+        assert!(record_error.path.ends_with(file_name));
+        assert_eq!(record_error.index, 1);
+        assert_eq!(record_error.field.as_deref(), Some(field));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_lenient_import_skips_and_reports_malformed_records() -> Result<(), Box<dyn Error>> {
+    let folder = temporary_export(
+        "lenient",
+        &[
+            (
+                "Streaming_History_Audio_2023.json",
+                serde_json::json!([
+                    extended_record("yesterday", "Artist", "Song", "spotify:track:a"),
+                    extended_record("2023-01-01T12:00:00Z", "Artist", "Song", "spotify:track:a"),
+                ]),
+            ),
+            (
+                "StreamingHistory_music_0.json",
+                serde_json::json!([
+                    legacy_music_record("2023-01-02 12:00"),
+                    legacy_music_record("yesterday"),
+                ]),
+            ),
+        ],
+    )?;
+    let result = CompressedEndStreamWithKindContainer::read_folder(
+        &folder,
+        &ImportOptions {
+            lenient: true,
+            ..Default::default()
+        },
+    );
+    std::fs::remove_dir_all(&folder)?;
+    let (data, report) = result?;
+    assert_eq!(report.total_records(), 2);
+    let plays: usize = data.into_iter().map(|x| x.7.play_count()).sum();
+    assert_eq!(plays, 2);
+    let quarantined: Vec<(usize, Option<&str>)> = report
+        .quarantined
+        .iter()
+        .map(|x| (x.index, x.field.as_deref()))
+        .collect();
+    assert_eq!(quarantined, [(1, Some("endTime")), (0, Some("ts"))]);
+    Ok(())
+}
+
+#[test]
+fn test_legacy_export_reads_music_and_podcasts() -> Result<(), Box<dyn Error>> {
+    let content = std::fs::read_to_string(format!("{LEGACY_DATA_FOLDER}/StreamingHistory0.json"))?;
+    let records: LegacyStreamContainer = serde_json::from_str(&content)?;
+    let serialized = serde_json::to_string(&records)?;
+    assert_eq!(
+        serde_json::from_str::<LegacyStreamContainer>(&serialized)?,
+        records
+    );
+
+    let (data, _) =
+        CompressedEndStreamWithKindContainer::read_folder(LEGACY_DATA_FOLDER, &Default::default())?;
+    let streams: Vec<(EndStreamKind, String, String, String, usize)> = data
+        .into_iter()
+        .map(|(_, _, _, kind, artist, album, track, info)| {
+            (kind, artist, album, track, info.play_count())
+        })
+        .collect();
+    assert_eq!(
+        streams,
+        [
+            (
+                EndStreamKind::EndSong,
+                "Maan".to_string(),
+                String::new(),
+                "Stiekem".to_string(),
+                2
+            ),
+            (
+                EndStreamKind::EndEpisode,
+                "The Daily".to_string(),
+                "The Daily".to_string(),
+                "A Year In Review".to_string(),
+                1
+            ),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_wrapped_days_stay_within_the_year() -> Result<(), Box<dyn Error>> {
+    // Two minutes ending one minute into the year, so it started on New Year's Eve.
+    let records = extended_records(vec![extended_record(
+        "2023-01-01T00:01:00Z",
+        "Artist",
+        "Song",
+        "spotify:track:a",
+    )])?;
+    let data = CompressedEndStreamWithKindContainer::from(records);
+    let wrapped = Wrapped::new(data, &DateRange::year(2023)?, 5);
+    let new_year = chrono::NaiveDate::from_ymd_opt(2023, 1, 1).ok_or("invalid date")?;
+    assert_eq!(
+        wrapped.busiest_day,
+        Some((new_year, chrono::Duration::minutes(1)))
+    );
+    let streak = wrapped.longest_streak.ok_or("no streak")?;
+    assert_eq!((streak.start, streak.days()), (new_year, 1));
+    Ok(())
+}
+
+#[test]
+fn test_period_arguments() -> Result<(), Box<dyn Error>> {
+    use clap::Parser;
+
+    let day = chrono::NaiveDate::from_ymd_opt(2023, 1, 31).ok_or("invalid date")?;
+    let range = DateRange::new(None, Some(super::parse_until("2023-01-31")?));
+    assert!(range.contains(&day.and_hms_opt(23, 59, 59).ok_or("invalid time")?));
+    assert!(!range.contains(
+        &(day + chrono::Duration::days(1))
+            .and_hms_opt(0, 0, 0)
+            .ok_or("invalid time")?
+    ));
+    let range = DateRange::new(None, Some(super::parse_until("2023-01-31T12:00:00")?));
+    assert!(range.contains(&day.and_hms_opt(12, 0, 0).ok_or("invalid time")?));
+    assert!(!range.contains(&day.and_hms_opt(12, 0, 1).ok_or("invalid time")?));
+
+    assert_eq!(super::parse_duration("90d")?, chrono::Duration::days(90));
+    assert_eq!(super::parse_duration("12w")?, chrono::Duration::weeks(12));
+    assert_eq!(super::parse_duration("48h")?, chrono::Duration::hours(48));
+    assert!(super::parse_duration("5y").is_err());
+    assert!(super::parse_duration("d").is_err());
+    assert!(super::parse_duration("-5m").is_err());
+    assert!(super::parse_duration("999999999999999w").is_err());
+
+    let now = day.and_hms_opt(12, 0, 0).ok_or("invalid time")?;
+    let args = super::SpotifyStats::try_parse_from(["spotify_stats", "--last", "7d", "benchmark"])?;
+    assert_eq!(
+        args.period.range(now)?.start,
+        Some(now - chrono::Duration::days(7))
+    );
+    for period in [
+        ["--since", "2023-02-01", "--until", "2023-01-01"],
+        ["--year", "2022", "--since", "2023-01-01"],
+        ["--last", "200000000d", "--since", "2023-01-01"],
+    ] {
+        let args = super::SpotifyStats::try_parse_from(
+            ["spotify_stats"]
+                .into_iter()
+                .chain(period)
+                .chain(["benchmark"]),
+        )?;
+        assert!(args.period.range(now).is_err());
+    }
+    for last in ["999999999999999w", "-5m"] {
+        assert!(super::SpotifyStats::try_parse_from([
+            "spotify_stats",
+            "--last",
+            last,
+            "benchmark"
+        ])
+        .is_err());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_zip_only_reads_streaming_history_members() -> Result<(), Box<dyn Error>> {
+    use std::io::Write as _;
+
+    let folder = temporary_export("zip", &[])?;
+    let archive = folder.join("my_spotify_data.zip");
+    let mut writer = zip::ZipWriter::new(std::fs::File::create(&archive)?);
+    let options = zip::write::FileOptions::default();
+    let members = [
+        (
+            "Spotify Extended Streaming History/Streaming_History_Audio_2023.json",
+            serde_json::json!([
+                extended_record("2023-01-01T12:00:00Z", "Artist", "Song", "spotify:track:a"),
+                extended_record("2023-01-01T12:05:00Z", "Artist", "Song", "spotify:track:a"),
+            ]),
+        ),
+        (
+            "Spotify Extended Streaming History/nested/Streaming_History_Audio_2024.json",
+            serde_json::json!([extended_record(
+                "2024-01-01T12:00:00Z",
+                "Artist",
+                "Other Song",
+                "spotify:track:b"
+            )]),
+        ),
+        // Not a streaming history file, even though it is JSON.
+        (
+            "Spotify Account Data/Userdata.json",
+            serde_json::json!({"username": "user"}),
+        ),
+    ];
+    writer.add_directory("Spotify Extended Streaming History/", options)?;
+    for (name, content) in members {
+        writer.start_file(name, options)?;
+        writer.write_all(serde_json::to_string(&content)?.as_bytes())?;
+    }
+    writer.start_file(
+        "Spotify Extended Streaming History/ReadMeFirst_ExtendedStreamingHistory.pdf",
+        options,
+    )?;
+    writer.write_all(b"%PDF-1.4")?;
+    writer.finish()?;
+
+    let result = CompressedEndStreamWithKindContainer::read_zip(&archive, &Default::default());
+    std::fs::remove_dir_all(&folder)?;
+    let (data, report) = result?;
+    let files: Vec<(&str, usize)> = report
+        .files
+        .iter()
+        .map(|x| (x.path.to_str().unwrap_or_default(), x.records))
+        .collect();
+    assert_eq!(
+        files,
+        [
+            (
+                "Spotify Extended Streaming History/Streaming_History_Audio_2023.json",
+                2
+            ),
+            (
+                "Spotify Extended Streaming History/nested/Streaming_History_Audio_2024.json",
+                1
+            ),
+        ]
+    );
+    assert_eq!(report.skipped.len(), 2);
+    let plays: usize = data.into_iter().map(|x| x.7.play_count()).sum();
+    assert_eq!(plays, 3);
+    Ok(())
+}
+
+#[test]
+fn test_read_folder_finds_nested_streaming_history() -> Result<(), Box<dyn Error>> {
+    let folder = temporary_export(
+        "nested",
+        &[
+            (
+                "MyData/Spotify Extended Streaming History/Streaming_History_Audio_2023.json",
+                serde_json::json!([extended_record(
+                    "2023-01-01T12:00:00Z",
+                    "Artist",
+                    "Song",
+                    "spotify:track:a"
+                )]),
+            ),
+            (
+                "MyData/Spotify Extended Streaming History/2024/Streaming_History_Audio_2024.json",
+                serde_json::json!([
+                    extended_record("2024-01-01T12:00:00Z", "Artist", "Song", "spotify:track:a"),
+                    extended_record("2024-01-01T12:05:00Z", "Artist", "Song", "spotify:track:a"),
+                ]),
+            ),
+            (
+                "MyData/Spotify Account Data/Userdata.json",
+                serde_json::json!({"username": "user"}),
+            ),
+        ],
+    )?;
+    std::fs::write(folder.join("MyData/ReadMeFirst.pdf"), b"%PDF-1.4")?;
+
+    let result = EndStreamWithKindContainer::read_folder(&folder, &Default::default());
+    let relative = |x: &std::path::Path| x.strip_prefix(&folder).map(|x| x.to_path_buf());
+    let outcome = result
+        .map_err(Box::<dyn Error>::from)
+        .and_then(|(records, report)| {
+            let files = report
+                .files
+                .iter()
+                .map(|x| Ok((relative(&x.path)?, x.records)))
+                .collect::<Result<Vec<_>, std::path::StripPrefixError>>()?;
+            let skipped = report
+                .skipped
+                .iter()
+                .map(|x| relative(x))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok((records, files, skipped))
+        });
+    std::fs::remove_dir_all(&folder)?;
+    let (records, files, skipped) = outcome?;
+    assert_eq!(records.0.len(), 3);
+    assert_eq!(
+        files,
+        [
+            (
+                "MyData/Spotify Extended Streaming History/2024/Streaming_History_Audio_2024.json"
+                    .into(),
+                2
+            ),
+            (
+                "MyData/Spotify Extended Streaming History/Streaming_History_Audio_2023.json"
+                    .into(),
+                1
+            ),
+        ]
+    );
+    assert_eq!(
+        skipped,
+        [
+            std::path::PathBuf::from("MyData/ReadMeFirst.pdf"),
+            "MyData/Spotify Account Data/Userdata.json".into(),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_changed_export_keeps_the_imported_exports() -> Result<(), Box<dyn Error>> {
+    let first = extended_record("2023-01-01T12:00:00Z", "Artist", "Song", "spotify:track:a");
+    let second = extended_record("2024-01-01T12:00:00Z", "Other", "Song", "spotify:track:b");
+    let newer = extended_record("2024-02-01T12:00:00Z", "Other", "Song", "spotify:track:b");
+    let folder = temporary_export(
+        "changed",
+        &[
+            (
+                "first/Streaming_History_Audio_2023.json",
+                serde_json::json!([first]),
+            ),
+            (
+                "second/Streaming_History_Audio_2024.json",
+                serde_json::json!([second]),
+            ),
+        ],
+    )?;
+    let result = (|| -> Result<(), Box<dyn Error>> {
+        let settings = super::CacheSettings {
+            path: folder.join("cache.bin"),
+            policy: super::CachePolicy::Use,
+            backup: false,
+            codec: Codec::default(),
+        };
+        let options = ImportOptions::default();
+        super::import_data(folder.join("first"), &settings, &options)?;
+        super::import_data(folder.join("second"), &settings, &options)?;
+        let imported = Cache::load_from_file(&settings.path)?;
+        assert_eq!(imported.sources.len(), 2);
+
+        std::fs::write(
+            folder.join("second/Streaming_History_Audio_2024.json"),
+            serde_json::to_string(&serde_json::json!([second, newer]))?,
+        )?;
+        assert!(super::init_data(Some(folder.join("second")), &settings, &options).is_err());
+        assert_eq!(Cache::load_from_file(&settings.path)?, imported);
+
+        let rebuild = super::CacheSettings {
+            policy: super::CachePolicy::Rebuild,
+            ..settings.clone()
+        };
+        super::init_data(Some(folder.join("second")), &rebuild, &options)?;
+        assert_eq!(Cache::load_from_file(&settings.path)?.sources.len(), 1);
+        assert_eq!(
+            Cache::load_from_file(backup_path(&settings.path))?,
+            imported
+        );
+        Ok(())
+    })();
+    std::fs::remove_dir_all(&folder)?;
+    result
+}